@@ -0,0 +1,189 @@
+use std::path::PathBuf;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+/// Headless stimulus generation with the Scramblery engine.
+///
+/// Scramble options come from `--config` (JSON or TOML, deserialized into
+/// `ScrambleOptions`) and can be overridden with the flags below. The config only holds
+/// scramble options; batch, video, equalize and noise settings are set with flags.
+#[derive(Debug, Parser)]
+#[command(name = "scramblery-cli", version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Scramble a single image
+    Image {
+        #[arg(short, long)]
+        input: PathBuf,
+        #[arg(short, long)]
+        output: PathBuf,
+        #[command(flatten)]
+        scramble: ScrambleArgs,
+    },
+    /// Scramble every image in a directory
+    Batch {
+        #[arg(short, long)]
+        input_dir: PathBuf,
+        #[arg(short, long)]
+        output_dir: PathBuf,
+        /// How each image's seed is chosen from --seed
+        #[arg(long, default_value = "same")]
+        seed_strategy: SeedStrategyArg,
+        /// Normalize every output towards the set average after scrambling
+        #[arg(long)]
+        normalize: Option<Normalize>,
+        /// Write spectra, phase maps and filter masks of Fourier-scrambled images here
+        #[arg(long)]
+        spectrum_output_dir: Option<PathBuf>,
+        #[command(flatten)]
+        scramble: ScrambleArgs,
+    },
+    /// Combine the amplitude spectrum of one image with the phase spectrum of another
+    Swap {
+        /// Image providing the amplitude spectrum
+        #[arg(long)]
+        amplitude: PathBuf,
+        /// Image providing the phase spectrum
+        #[arg(long)]
+        phase: PathBuf,
+        #[arg(short, long)]
+        output: PathBuf,
+        /// 0 keeps the phase image's amplitudes, 1 takes the amplitude image's
+        #[arg(long, default_value_t = 1.0)]
+        amplitude_weight: f32,
+        /// 0 keeps the amplitude image's phases, 1 takes the phase image's
+        #[arg(long, default_value_t = 1.0)]
+        phase_weight: f32,
+        #[arg(long)]
+        grayscale: bool,
+    },
+    /// Give every image in a directory the same amplitude spectrum (SHINE specMatch/sfMatch)
+    Equalize {
+        #[arg(short, long)]
+        input_dir: PathBuf,
+        #[arg(short, long)]
+        output_dir: PathBuf,
+        /// Match the full 2D spectrum or only its rotational average
+        #[arg(long, default_value = "full")]
+        mode: SpectrumMode,
+        /// Match luminance only and write grayscale images
+        #[arg(long)]
+        grayscale: bool,
+        /// Save the mean amplitude spectrum (.json for raw values, otherwise an image)
+        #[arg(long)]
+        spectrum_output: Option<PathBuf>,
+    },
+    /// Generate a 1/f^alpha noise image, or noise with the amplitude spectrum of an image
+    Noise {
+        #[arg(short, long)]
+        output: PathBuf,
+        #[arg(long, default_value_t = 256)]
+        width: u32,
+        #[arg(long, default_value_t = 256)]
+        height: u32,
+        /// Spectral slope: 0 is white noise, 1 pink noise, 2 brown noise
+        #[arg(long, default_value_t = 1.0)]
+        alpha: f32,
+        /// Match the amplitude spectrum of this image instead of using --alpha
+        #[arg(long = "match")]
+        match_image: Option<PathBuf>,
+        /// RMS contrast of 1/f^alpha noise
+        #[arg(long, default_value_t = 0.2)]
+        contrast: f32,
+        /// Write three color channels instead of one luminance channel
+        #[arg(long)]
+        color: bool,
+        /// Use the same random phase for every color channel
+        #[arg(long)]
+        shared_phase: bool,
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// Scramble every frame of a video
+    Video {
+        #[arg(short, long)]
+        input: PathBuf,
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Enable temporal coherence with a fresh keyframe every N frames
+        #[arg(long)]
+        keyframe_interval: Option<usize>,
+        /// Frames to crossfade around each keyframe (requires --keyframe-interval)
+        #[arg(long, default_value_t = 0, requires = "keyframe_interval")]
+        blend_frames: usize,
+        /// Export optical flow fields as .flo files into this directory
+        #[arg(long)]
+        flow_output_dir: Option<PathBuf>,
+        #[command(flatten)]
+        scramble: ScrambleArgs,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct ScrambleArgs {
+    /// JSON or TOML file holding `ScrambleOptions` (quote seeds above i64::MAX in TOML)
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+    /// Scramble method, using its default options
+    #[arg(short, long)]
+    pub method: Option<Method>,
+    #[arg(long)]
+    pub intensity: Option<f32>,
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Only scramble detected faces
+    #[arg(long)]
+    pub faces: bool,
+    /// Minimum face detection confidence (implies --faces)
+    #[arg(long)]
+    pub confidence: Option<f32>,
+    /// What to do with pixels outside the scrambled regions (implies --faces without a region mask)
+    #[arg(long)]
+    pub background: Option<Background>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Method {
+    Pixel,
+    Fourier,
+    Block,
+    Blur,
+    Diffeomorphic,
+    Wavelet,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum SeedStrategyArg {
+    /// Every image uses --seed, or a fresh random seed per image without it
+    Same,
+    /// Seed derived from the base seed and the file name
+    FileName,
+    /// Seed derived from the base seed and the sorted file index
+    Index,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Normalize {
+    /// Mean luminance and RMS contrast (lumMatch)
+    Lum,
+    /// Exact luminance histogram (histMatch)
+    Hist,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum SpectrumMode {
+    /// Mean 2D amplitude spectrum (specMatch)
+    Full,
+    /// Mean rotational average (sfMatch)
+    Rotational,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Background {
+    Include,
+    Exclude,
+}
@@ -0,0 +1,236 @@
+mod args;
+
+use std::io::Write;
+use std::path::Path;
+use std::process::ExitCode;
+use anyhow::Context;
+use clap::Parser;
+use engine::batch::{
+    BatchProcessingOptions, BatchProgress, ProcessingResult, SeedStrategy, SpectrumMatchMode, SpectrumMatchOptions,
+};
+use engine::scramble::{
+    BackgroundMode, BlockOptions, BlurOptions, DiffeomorphicOptions, FaceDetectionOptions,
+    FourierOptions, HistogramTarget, NoiseOptions, NoiseSpectrum, NormalizationMethod, NormalizationOptions,
+    PhaseMode, ScrambleOptions, ScrambleType, SpectrumSwapOptions, TemporalCoherenceOptions, WaveletOptions,
+};
+use engine::video::VideoProcessingOptions;
+use args::{Background, Cli, Command, Method, Normalize, ScrambleArgs, SeedStrategyArg, SpectrumMode};
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli.command) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(command: Command) -> anyhow::Result<ExitCode> {
+    match command {
+        Command::Image { input, output, scramble } => {
+            let options = scramble_options(&scramble)?;
+            engine::batch::process_single_image(&input, &output, &options)?;
+            eprintln!("Saved {}", output.display());
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Batch { input_dir, output_dir, seed_strategy, normalize, spectrum_output_dir, scramble } => {
+            let options = BatchProcessingOptions {
+                input_dir,
+                output_dir,
+                scramble_options: scramble_options(&scramble)?,
+                seed_strategy: Some(match seed_strategy {
+                    SeedStrategyArg::Same => SeedStrategy::Same,
+                    SeedStrategyArg::FileName => SeedStrategy::FileName,
+                    SeedStrategyArg::Index => SeedStrategy::Index,
+                }),
+                normalization: normalize.map(|normalize| NormalizationOptions {
+                    method: match normalize {
+                        Normalize::Lum => NormalizationMethod::MeanStd { mean: None, std: None },
+                        Normalize::Hist => NormalizationMethod::Histogram(HistogramTarget::Reference),
+                    },
+                    within_mask: false,
+                }),
+                spectrum_output_dir,
+            };
+            let results = engine::batch::process_directory_with_progress(&options, print_progress)?;
+            Ok(report_results(&results))
+        }
+        Command::Swap { amplitude, phase, output, amplitude_weight, phase_weight, grayscale } => {
+            let open = |path: &Path| {
+                image::open(path).with_context(|| format!("Failed to open image: {}", path.display()))
+            };
+            let fourier_opts = FourierOptions { grayscale, ..FourierOptions::default() };
+            let swap_opts = SpectrumSwapOptions { amplitude_weight, phase_weight };
+            let swapped = engine::scramble::swap_spectra(&open(&amplitude)?, &open(&phase)?, &fourier_opts, &swap_opts)?;
+            swapped.save(&output)
+                .with_context(|| format!("Failed to save image: {}", output.display()))?;
+            eprintln!("Saved {}", output.display());
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Equalize { input_dir, output_dir, mode, grayscale, spectrum_output } => {
+            let options = SpectrumMatchOptions {
+                input_dir,
+                output_dir,
+                mode: match mode {
+                    SpectrumMode::Full => SpectrumMatchMode::Full,
+                    SpectrumMode::Rotational => SpectrumMatchMode::Rotational,
+                },
+                grayscale,
+                spectrum_output,
+            };
+            let results = engine::batch::equalize_spectra(&options, print_progress)?;
+            Ok(report_results(&results))
+        }
+        Command::Noise { output, width, height, alpha, match_image, contrast, color, shared_phase, seed } => {
+            let options = NoiseOptions {
+                width,
+                height,
+                spectrum: match_image.map_or(NoiseSpectrum::PowerLaw(alpha), NoiseSpectrum::Image),
+                grayscale: !color,
+                contrast,
+                phase_mode: shared_phase.then_some(PhaseMode::Shared),
+                seed,
+            };
+            let noise = engine::scramble::generate_noise(&options)?;
+            noise.save(&output)
+                .with_context(|| format!("Failed to save image: {}", output.display()))?;
+            eprintln!("Saved {}", output.display());
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Video { input, output, keyframe_interval, blend_frames, flow_output_dir, scramble } => {
+            let temporal_coherence = keyframe_interval.map(|keyframe_interval| TemporalCoherenceOptions {
+                export_flow: flow_output_dir.is_some(),
+                flow_output_dir,
+                keyframe_interval,
+                blend_frames,
+            });
+            let options = VideoProcessingOptions {
+                input_path: input,
+                output_path: output.clone(),
+                scramble_options: scramble_options(&scramble)?,
+                temporal_coherence,
+            };
+            engine::video::process_video(&options, |progress| {
+                eprint!("\rProcessing video: {:5.1}%", progress * 100.0);
+                let _ = std::io::stderr().flush();
+            })?;
+            eprintln!("\nSaved {}", output.display());
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
+
+fn print_progress(progress: BatchProgress) {
+    if let Some(file) = &progress.current_file {
+        eprintln!("[{}/{}] {}", progress.processed_files, progress.total_files, file.display());
+    }
+}
+
+/// Prints failed files and a summary; fails if any file failed.
+fn report_results(results: &[ProcessingResult]) -> ExitCode {
+    let failed: Vec<_> = results.iter().filter(|result| !result.success).collect();
+    for result in &failed {
+        eprintln!(
+            "failed: {}: {}",
+            result.input_path.display(),
+            result.error.as_deref().unwrap_or("unknown error")
+        );
+    }
+    eprintln!("Processed {} image(s), {} failed", results.len(), failed.len());
+    if failed.is_empty() { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
+
+/// Builds `ScrambleOptions` from the config file (if any), then applies flag overrides.
+fn scramble_options(args: &ScrambleArgs) -> anyhow::Result<ScrambleOptions> {
+    let mut options = match &args.config {
+        Some(path) => load_config(path)?,
+        None => ScrambleOptions::default(),
+    };
+    if let Some(method) = args.method {
+        options.scramble_type = match method {
+            Method::Pixel => ScrambleType::Pixel,
+            Method::Fourier => ScrambleType::Fourier(FourierOptions::default()),
+            Method::Block => ScrambleType::Block(BlockOptions::default()),
+            Method::Blur => ScrambleType::Blur(BlurOptions::default()),
+            Method::Diffeomorphic => ScrambleType::Diffeomorphic(DiffeomorphicOptions::default()),
+            Method::Wavelet => ScrambleType::Wavelet(WaveletOptions::default()),
+        };
+    }
+    if let Some(intensity) = args.intensity {
+        options.intensity = intensity;
+    }
+    if args.seed.is_some() {
+        options.seed = args.seed;
+    }
+
+    let background_mode = args.background.map(|background| match background {
+        Background::Include => BackgroundMode::Include,
+        Background::Exclude => BackgroundMode::Exclude,
+    });
+    if let (Some(mask_opts), Some(mode)) = (options.region_mask.as_mut(), background_mode.clone()) {
+        mask_opts.background_mode = mode;
+    } else if args.faces || args.confidence.is_some() || background_mode.is_some() {
+        let face_opts = options.face_detection.get_or_insert_with(FaceDetectionOptions::default);
+        if let Some(confidence) = args.confidence {
+            face_opts.confidence_threshold = confidence;
+        }
+        if let Some(mode) = background_mode {
+            face_opts.background_mode = mode;
+        }
+    }
+    Ok(options)
+}
+
+/// Reads `ScrambleOptions` from a `.toml` file, or from JSON for any other extension.
+fn load_config(path: &Path) -> anyhow::Result<ScrambleOptions> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config: {}", path.display()))?;
+    let is_toml = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("toml"));
+    if is_toml {
+        // TOML integers are signed 64-bit, so larger seeds fail to parse unless quoted
+        let unparsable = || {
+            format!(
+                "Invalid TOML config: {} (seeds above {} must be quoted, e.g. seed = \"{}\")",
+                path.display(),
+                i64::MAX,
+                u64::MAX
+            )
+        };
+        let mut value = serde_json::to_value(toml::from_str::<toml::Value>(&contents).with_context(unparsable)?)?;
+        parse_quoted_seeds(&mut value)?;
+        serde_json::from_value(value).with_context(|| format!("Invalid TOML config: {}", path.display()))
+    } else {
+        serde_json::from_str(&contents).with_context(|| format!("Invalid JSON config: {}", path.display()))
+    }
+}
+
+/// TOML integers stop at `i64::MAX`, so larger `u64` seeds are written as strings. Turns the
+/// string values of `seed` and `Fixed` (pipeline step seed) keys back into numbers.
+fn parse_quoted_seeds(value: &mut serde_json::Value) -> anyhow::Result<()> {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                match value {
+                    serde_json::Value::String(seed) if key == "seed" || key == "Fixed" => {
+                        let seed: u64 = seed
+                            .parse()
+                            .with_context(|| format!("Invalid seed \"{}\", expected an integer up to {}", seed, u64::MAX))?;
+                        *value = seed.into();
+                    }
+                    _ => parse_quoted_seeds(value)?,
+                }
+            }
+        }
+        serde_json::Value::Array(values) => {
+            for value in values.iter_mut() {
+                parse_quoted_seeds(value)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
@@ -1,21 +1,32 @@
 mod types;
+mod spectrum;
 pub use types::*;
+pub use spectrum::{equalize_spectra, AmplitudeSpectrum};
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use rayon::prelude::*;
 use anyhow::Context;
 use crate::Result;
-use crate::ScrambleType;
 use crate::ScrambleOptions;
+use crate::scramble::derive_seed;
+use crate::manifest::{hash_file, resolve_seed, write_sidecar, ImageManifest, RegionRecord, ENGINE_VERSION};
+use crate::scramble::{
+    average_stats, luminance_stats, normalize_luminance, FourierOptions, FourierScrambler, LuminanceStats,
+    NormalizationOptions, RegionMask, ScrambleType,
+};
+use face_detection::FaceRegion;
+use image::DynamicImage;
 pub fn process_directory(options: &BatchProcessingOptions) -> Result<Vec<ProcessingResult>> {
+    process_directory_with_progress(options, |_| {})
+}
 
-    std::fs::create_dir_all(&options.output_dir)?;
-    
-
-    let entries: Vec<_> = std::fs::read_dir(&options.input_dir)?
+/// Lists the supported images in `dir`, sorted by file name so that indices are stable.
+fn list_images(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(dir)?
         .filter_map(|entry| entry.ok())
-        .filter(|entry| {
-            if let Some(ext) = entry.path().extension() {
+        .map(|entry| entry.path())
+        .filter(|path| {
+            if let Some(ext) = path.extension() {
                 matches!(ext.to_str().unwrap_or("").to_lowercase().as_str(),
                     "jpg" | "jpeg" | "png" | "gif" | "webp")
             } else {
@@ -23,98 +34,96 @@ pub fn process_directory(options: &BatchProcessingOptions) -> Result<Vec<Process
             }
         })
         .collect();
+    entries.sort();
+    Ok(entries)
+}
 
-    
-    let results: Vec<ProcessingResult> = entries.par_iter()
-        .map(|entry| {
-            let input_path = entry.path();
-            let file_name = input_path.file_name().unwrap();
-            let output_path = options.output_dir.join(file_name);
-            
-            match process_single_image(&input_path, &output_path, &options.scramble_options) {
-                Ok(_) => ProcessingResult {
-                    input_path: input_path.to_owned(),
-                    output_path: output_path.to_owned(),
-                    success: true,
-                    error: None,
-                },
-                Err(e) => ProcessingResult {
-                    input_path: input_path.to_owned(),
-                    output_path: output_path.to_owned(),
-                    success: false,
-                    error: Some(e.to_string()),
-                },
-            }
-        })
-        .collect();
+/// Base seed of the run for the strategies that derive per-image seeds, `None` for
+/// `SeedStrategy::Same`.
+fn base_seed(options: &BatchProcessingOptions) -> Option<u64> {
+    match options.seed_strategy.as_ref().unwrap_or(&SeedStrategy::Same) {
+        SeedStrategy::Same => None,
+        SeedStrategy::FileName | SeedStrategy::Index => {
+            Some(options.scramble_options.seed.unwrap_or_else(rand::random))
+        }
+    }
+}
 
-    Ok(results)
+/// Options for the image at `index`, with its seed chosen by the batch `seed_strategy`.
+/// Derived seeds depend only on `base_seed` and the file, never on scheduling order.
+fn file_scramble_options(
+    options: &BatchProcessingOptions,
+    base_seed: Option<u64>,
+    index: usize,
+    input_path: &Path,
+) -> ScrambleOptions {
+    let mut file_options = options.scramble_options.clone();
+    let Some(base_seed) = base_seed else {
+        return file_options;
+    };
+    match options.seed_strategy.as_ref().unwrap_or(&SeedStrategy::Same) {
+        SeedStrategy::Same => {}
+        SeedStrategy::FileName => {
+            let file_name = input_path.file_name().unwrap_or_default().to_string_lossy();
+            file_options.seed = Some(derive_seed(base_seed, fnv1a_hash(file_name.as_bytes())));
+        }
+        SeedStrategy::Index => {
+            file_options.seed = Some(derive_seed(base_seed, index as u64));
+        }
+    }
+    file_options
 }
 
-fn process_single_image(
+/// 64-bit FNV-1a hash, stable across platforms and Rust versions.
+fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Scrambles the image at `input_path`, saves it to `output_path` and writes
+/// a reproducibility manifest next to it (see `manifest::sidecar_path`).
+pub fn process_single_image(
     input_path: &Path,
     output_path: &Path,
     options: &ScrambleOptions,
-) -> Result<()> {
+) -> Result<ImageManifest> {
+    let (manifest, image) = scramble_file(input_path, output_path, options)?;
+    save_output(&image, &manifest)?;
+    Ok(manifest)
+}
+
+/// Scrambles the image at `input_path` without saving it, returning the manifest that
+/// describes the output to be written to `output_path`.
+fn scramble_file(
+    input_path: &Path,
+    output_path: &Path,
+    options: &ScrambleOptions,
+) -> Result<(ImageManifest, DynamicImage)> {
     let img = image::open(input_path)
         .with_context(|| format!("Failed to open image: {}", input_path.display()))?;
 
-    let scrambled = match &options.scramble_type {
-        ScrambleType::Pixel => crate::scramble::scramble_pixels(&img, options)?,
-        ScrambleType::Fourier(fourier_opts) => {
-            let mut scrambler = crate::scramble::FourierScrambler::new(
-                img.width() as usize,
-                img.height() as usize,
-                fourier_opts.clone(),
-                options.seed,
-            );
-            
-            if let Some(face_opts) = &options.face_detection {
-                scrambler.scramble_with_face_detection(&img, face_opts)?
-            } else {
-                scrambler.scramble(&img)?
-            }
-        },
-        ScrambleType::Block(block_opts) => {
-            let mut scrambler = crate::scramble::BlockScrambler::new(
-                block_opts.clone(),
-                options.seed,
-            );
-            
-            if let Some(face_opts) = &options.face_detection {
-                scrambler.scramble_with_face_detection(&img, face_opts)?
-            } else {
-                scrambler.scramble(&img)?
-            }
-        },
-        ScrambleType::Blur(blur_opts) => {
-            let scrambler = crate::scramble::BlurScrambler::new(
-                blur_opts.clone(),
-            );
-
-            if let Some(face_opts) = &options.face_detection {
-                scrambler.scramble_with_face_detection(&img, face_opts)?
-            } else {
-                scrambler.scramble(&img)?
-            }
-        }
-        ScrambleType::Diffeomorphic(diff_opts) => {
-            let mut scrambler = crate::scramble::DiffeomorphicScrambler::new(
-                diff_opts.clone(),
-                options.seed,
-            );
-
-            if let Some(face_opts) = &options.face_detection {
-                scrambler.scramble_with_face_detection(&img, face_opts)?
-            } else {
-                scrambler.scramble(&img)?
-            }
-        }
-    };
+    let (options, effective_seed) = resolve_seed(options);
+    let scrambled = crate::scramble::scramble_image_with_regions(&img, &options)?;
 
-    scrambled.save(output_path)
-        .with_context(|| format!("Failed to save image: {}", output_path.display()))?;
+    let manifest = ImageManifest {
+        engine_version: ENGINE_VERSION.to_string(),
+        input_path: input_path.to_owned(),
+        input_sha256: hash_file(input_path)?,
+        output_path: output_path.to_owned(),
+        scramble_options: options,
+        effective_seed,
+        face_regions: scrambled.face_regions.iter().map(RegionRecord::from).collect(),
+        batch_normalization: None,
+    };
+    Ok((manifest, scrambled.image))
+}
 
+/// Saves `image` to the manifest's output path and writes the manifest next to it.
+fn save_output(image: &DynamicImage, manifest: &ImageManifest) -> Result<()> {
+    image.save(&manifest.output_path)
+        .with_context(|| format!("Failed to save image: {}", manifest.output_path.display()))?;
+    write_sidecar(manifest, &manifest.output_path)?;
     Ok(())
 }
 
@@ -126,29 +135,31 @@ where
     F: ProgressCallback,
 {
     std::fs::create_dir_all(&options.output_dir)?;
-    let entries: Vec<_> = std::fs::read_dir(&options.input_dir)?
-        .filter_map(|entry| entry.ok())
-        .filter(|entry| {
-            if let Some(ext) = entry.path().extension() {
-                matches!(ext.to_str().unwrap_or("").to_lowercase().as_str(),
-                    "jpg" | "jpeg" | "png" | "gif" | "webp")
-            } else {
-                false
-            }
-        })
-        .collect();
+    let entries = list_images(&options.input_dir)?;
+    let base_seed = base_seed(options);
 
     let total_files = entries.len();
     let processed = std::sync::atomic::AtomicUsize::new(0);
 
-    let results: Vec<ProcessingResult> = entries.par_iter()
-        .map(|entry| {
-            let input_path = entry.path();
-            let result = process_single_image(
-                &input_path,
-                &options.output_dir.join(input_path.file_name().unwrap()),
-                &options.scramble_options,
-            );
+    // With set normalization, this first pass only records the luminance statistics of each
+    // scrambled image; `normalize_set` scrambles the images again once the set average is known
+    let processed_files: Vec<_> = entries.par_iter()
+        .enumerate()
+        .map(|(index, input_path)| {
+            let output_path = options.output_dir.join(input_path.file_name().unwrap());
+            let file_options = file_scramble_options(options, base_seed, index, input_path);
+            let result = scramble_file(input_path, &output_path, &file_options).and_then(|(manifest, image)| {
+                match &options.normalization {
+                    Some(norm_opts) => {
+                        let mask = normalization_mask(norm_opts, &manifest, &image)?;
+                        Ok((manifest, Some(luminance_stats(&image, mask.as_ref()))))
+                    }
+                    None => {
+                        save_output(&image, &manifest)?;
+                        Ok((manifest, None))
+                    }
+                }
+            });
 
             let current_processed = processed.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
             progress_callback(BatchProgress {
@@ -158,22 +169,37 @@ where
             });
 
             match result {
-                Ok(_) => ProcessingResult {
+                Ok(output) => (ProcessingResult {
                     input_path: input_path.to_owned(),
-                    output_path: options.output_dir.join(input_path.file_name().unwrap()),
+                    output_path,
                     success: true,
                     error: None,
-                },
-                Err(e) => ProcessingResult {
+                }, Some(output)),
+                Err(e) => (ProcessingResult {
                     input_path: input_path.to_owned(),
-                    output_path: options.output_dir.join(input_path.file_name().unwrap()),
+                    output_path,
                     success: false,
                     error: Some(e.to_string()),
-                },
+                }, None),
             }
         })
         .collect();
 
+    let (mut results, outputs): (Vec<ProcessingResult>, Vec<_>) = processed_files.into_iter().unzip();
+    let manifests = match &options.normalization {
+        Some(norm_opts) => {
+            let scrambled = outputs
+                .into_iter()
+                .map(|output| output.and_then(|(manifest, stats)| Some((manifest, stats?))))
+                .collect();
+            normalize_set(norm_opts, &mut results, scrambled)
+        }
+        None => outputs.into_iter().map(|output| output.map(|(manifest, _)| manifest)).collect(),
+    };
+    if let Some(dir) = &options.spectrum_output_dir {
+        write_inspections(dir, &mut results, &manifests)?;
+    }
+
     progress_callback(BatchProgress {
         total_files,
         processed_files: total_files,
@@ -181,4 +207,102 @@ where
     });
 
     Ok(results)
-}
\ No newline at end of file
+}
+
+/// Normalizes every successfully scrambled image towards the average of the luminance
+/// statistics `scrambled` recorded for the set, records the normalization in its manifest and
+/// saves the image. Each image is scrambled again from its manifest (whose seed is resolved, so
+/// the output is the same), which keeps only the images being worked on in memory at the cost
+/// of scrambling every image twice. Returns the manifests of the saved images; failures are
+/// reported per image.
+fn normalize_set(
+    norm_opts: &NormalizationOptions,
+    results: &mut [ProcessingResult],
+    scrambled: Vec<Option<(ImageManifest, LuminanceStats)>>,
+) -> Vec<Option<ImageManifest>> {
+    let stats: Vec<LuminanceStats> = scrambled.iter().flatten().map(|(_, stats)| stats.clone()).collect();
+    let reference = average_stats(&stats);
+
+    let outcomes: Vec<Option<Result<ImageManifest>>> = scrambled
+        .into_par_iter()
+        .map(|entry| {
+            entry.map(|(manifest, _)| {
+                let (mut manifest, image) =
+                    scramble_file(&manifest.input_path, &manifest.output_path, &manifest.scramble_options)?;
+                let mask = normalization_mask(norm_opts, &manifest, &image)?;
+                let normalized = normalize_luminance(&image, norm_opts, &reference, mask.as_ref())?;
+                manifest.batch_normalization = Some(norm_opts.clone());
+                save_output(&normalized, &manifest)?;
+                Ok(manifest)
+            })
+        })
+        .collect();
+    results
+        .iter_mut()
+        .zip(outcomes)
+        .map(|(result, outcome)| match outcome? {
+            Ok(manifest) => Some(manifest),
+            Err(e) => {
+                result.success = false;
+                result.error = Some(e.to_string());
+                None
+            }
+        })
+        .collect()
+}
+
+/// Region that `norm_opts` normalizes in the scrambled `image`, `None` for the whole image.
+fn normalization_mask(
+    norm_opts: &NormalizationOptions,
+    manifest: &ImageManifest,
+    image: &DynamicImage,
+) -> Result<Option<RegionMask>> {
+    if !norm_opts.within_mask {
+        return Ok(None);
+    }
+    let face_regions: Vec<FaceRegion> = manifest.face_regions.iter().map(FaceRegion::from).collect();
+    RegionMask::for_options(&manifest.scramble_options, &face_regions, image.width(), image.height(), 0)
+}
+
+/// Writes the spectrum inspection of the input and output of every successfully scrambled
+/// Fourier image to `dir`. Failures are reported per image.
+fn write_inspections(
+    dir: &Path,
+    results: &mut [ProcessingResult],
+    manifests: &[Option<ImageManifest>],
+) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let errors: Vec<Option<String>> = manifests
+        .par_iter()
+        .map(|manifest| {
+            let manifest = manifest.as_ref()?;
+            let ScrambleType::Fourier(fourier_opts) = &manifest.scramble_options.scramble_type else {
+                return None;
+            };
+            write_inspection(dir, manifest, fourier_opts).err().map(|e| e.to_string())
+        })
+        .collect();
+    for (result, error) in results.iter_mut().zip(errors) {
+        if let Some(error) = error {
+            result.success = false;
+            result.error = Some(error);
+        }
+    }
+    Ok(())
+}
+
+fn write_inspection(dir: &Path, manifest: &ImageManifest, fourier_opts: &FourierOptions) -> Result<()> {
+    let stem = manifest.input_path.file_stem().unwrap_or_default().to_string_lossy();
+    for (image_path, suffix) in [(&manifest.input_path, "input"), (&manifest.output_path, "output")] {
+        let image = image::open(image_path)
+            .with_context(|| format!("Failed to open image: {}", image_path.display()))?;
+        let mut scrambler = FourierScrambler::new(
+            image.width() as usize,
+            image.height() as usize,
+            fourier_opts.clone(),
+            Some(manifest.effective_seed),
+        );
+        scrambler.inspect(&image)?.save(dir, &format!("{}_{}", stem, suffix))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,229 @@
+use std::path::Path;
+use anyhow::Context;
+use image::{DynamicImage, GrayImage, Luma, Rgb, RgbImage};
+use ndarray::Array2;
+use num_complex::Complex64;
+use rayon::prelude::*;
+use serde::{Serialize, Deserialize};
+use crate::Result;
+use crate::scramble::FrequencyUnits;
+use crate::scramble::fft::Fft2d;
+use crate::scramble::filter::{ring_indices, rotational_average, FrequencyGrid};
+use crate::scramble::inspect::centered_image;
+use super::{list_images, BatchProgress, ProcessingResult, ProgressCallback, SpectrumMatchMode, SpectrumMatchOptions};
+
+/// Amplitude spectrum of every channel, row-major `height x width` in FFT order (DC at index 0).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AmplitudeSpectrum {
+    pub width: usize,
+    pub height: usize,
+    pub channels: Vec<Vec<f64>>,
+}
+
+impl AmplitudeSpectrum {
+    /// Log-scaled image of the channel average, shifted so that DC is in the center.
+    pub fn to_image(&self) -> GrayImage {
+        let (width, height) = (self.width, self.height);
+        let mut log_amplitude = vec![0.0; width * height];
+        for channel in &self.channels {
+            for (value, amplitude) in log_amplitude.iter_mut().zip(channel) {
+                *value += (1.0 + amplitude).ln() / self.channels.len() as f64;
+            }
+        }
+        let max = log_amplitude.iter().cloned().fold(0.0, f64::max);
+        let scale = if max > 0.0 { 1.0 / max } else { 0.0 };
+        let normalized: Vec<f64> = log_amplitude.iter().map(|value| value * scale).collect();
+        centered_image(&normalized, width, height)
+    }
+
+    /// Writes the raw values as JSON for `.json` paths, otherwise the image from `to_image`.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let is_json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        if is_json {
+            std::fs::write(path, serde_json::to_string(self)?)
+                .with_context(|| format!("Failed to write spectrum: {}", path.display()))?;
+        } else {
+            self.to_image()
+                .save(path)
+                .with_context(|| format!("Failed to save spectrum: {}", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Equalizes the amplitude spectra of every image in `options.input_dir` and writes the
+/// results to `options.output_dir`.
+///
+/// The target spectrum is the mean over all images, so the whole set is read twice: once to
+/// compute the target and once to impose it. Any image that cannot be read or has a different
+/// size fails the whole run; saving errors are reported per image. Output values outside the
+/// displayable range are clipped, and with `SpectrumMatchMode::Full` the mean luminance of
+/// each image becomes the mean of the set, as the DC term is part of the spectrum.
+pub fn equalize_spectra<F>(options: &SpectrumMatchOptions, progress_callback: F) -> Result<Vec<ProcessingResult>>
+where
+    F: ProgressCallback,
+{
+    std::fs::create_dir_all(&options.output_dir)?;
+    let entries = list_images(&options.input_dir)?;
+    let Some(first) = entries.first() else {
+        return Ok(Vec::new());
+    };
+    let (width, height) = image::image_dimensions(first)
+        .with_context(|| format!("Failed to open image: {}", first.display()))?;
+    let (width, height) = (width as usize, height as usize);
+    let fft = Fft2d::new(height, width);
+    let grid = FrequencyGrid::new(height, width, (height, width), &FrequencyUnits::CyclesPerImage);
+    let rings = ring_indices(&grid, height, width);
+    let ring_count = rings.iter().max().map_or(0, |max| max + 1);
+    let channel_count = if options.grayscale { 1 } else { 3 };
+    let target_len = match options.mode {
+        SpectrumMatchMode::Full => height * width,
+        SpectrumMatchMode::Rotational => ring_count,
+    };
+
+    let sum = entries
+        .par_iter()
+        .map(|path| {
+            let channels = load_channels(path, options.grayscale, (width, height))?;
+            channels
+                .iter()
+                .map(|channel| {
+                    let amplitude: Vec<f64> = fft.forward(channel)?.iter().map(|c| c.norm()).collect();
+                    Ok(match options.mode {
+                        SpectrumMatchMode::Full => amplitude,
+                        SpectrumMatchMode::Rotational => rotational_average(&amplitude, &rings, ring_count),
+                    })
+                })
+                .collect::<Result<Vec<_>>>()
+        })
+        .try_reduce(
+            || vec![vec![0.0; target_len]; channel_count],
+            |mut a, b| {
+                for (a, b) in a.iter_mut().zip(b) {
+                    for (a, b) in a.iter_mut().zip(b) {
+                        *a += b;
+                    }
+                }
+                Ok(a)
+            },
+        )?;
+    let target: Vec<Vec<f64>> = sum
+        .into_iter()
+        .map(|channel| channel.into_iter().map(|value| value / entries.len() as f64).collect())
+        .collect();
+
+    if let Some(path) = &options.spectrum_output {
+        let channels = match options.mode {
+            SpectrumMatchMode::Full => target.clone(),
+            SpectrumMatchMode::Rotational => target
+                .iter()
+                .map(|channel| rings.iter().map(|&ring| channel[ring]).collect())
+                .collect(),
+        };
+        AmplitudeSpectrum { width, height, channels }.save(path)?;
+    }
+
+    let total_files = entries.len();
+    let processed = std::sync::atomic::AtomicUsize::new(0);
+    let results: Vec<ProcessingResult> = entries
+        .par_iter()
+        .map(|input_path| {
+            let output_path = options.output_dir.join(input_path.file_name().unwrap());
+            let result = load_channels(input_path, options.grayscale, (width, height)).and_then(|channels| {
+                let matched = channels
+                    .iter()
+                    .zip(&target)
+                    .map(|(channel, target)| {
+                        let mut spectrum = fft.forward(channel)?;
+                        match options.mode {
+                            SpectrumMatchMode::Full => impose_amplitude(&mut spectrum, target),
+                            SpectrumMatchMode::Rotational => impose_rotational_average(&mut spectrum, target, &rings),
+                        }
+                        fft.inverse(&spectrum)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                to_image(&matched)
+                    .save(&output_path)
+                    .with_context(|| format!("Failed to save image: {}", output_path.display()))
+            });
+
+            let current_processed = processed.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+            progress_callback(BatchProgress {
+                total_files,
+                processed_files: current_processed,
+                current_file: Some(input_path.clone()),
+            });
+
+            ProcessingResult {
+                input_path: input_path.to_owned(),
+                output_path,
+                success: result.is_ok(),
+                error: result.err().map(|e| e.to_string()),
+            }
+        })
+        .collect();
+
+    progress_callback(BatchProgress {
+        total_files,
+        processed_files: total_files,
+        current_file: None,
+    });
+
+    Ok(results)
+}
+
+/// Loads an image as one luminance channel or three RGB channels in `[0, 1]`.
+fn load_channels(path: &Path, grayscale: bool, expected: (usize, usize)) -> Result<Vec<Array2<f64>>> {
+    let img = image::open(path)
+        .with_context(|| format!("Failed to open image: {}", path.display()))?;
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    if (width, height) != expected {
+        anyhow::bail!(
+            "{} is {}x{}, but spectrum matching needs every image to be {}x{}",
+            path.display(), width, height, expected.0, expected.1
+        );
+    }
+    if grayscale {
+        let gray = img.to_luma8();
+        return Ok(vec![Array2::from_shape_fn((height, width), |(y, x)| {
+            gray.get_pixel(x as u32, y as u32)[0] as f64 / 255.0
+        })]);
+    }
+    let rgb = img.to_rgb8();
+    Ok((0..3)
+        .map(|c| Array2::from_shape_fn((height, width), |(y, x)| rgb.get_pixel(x as u32, y as u32)[c] as f64 / 255.0))
+        .collect())
+}
+
+fn to_image(channels: &[Array2<f64>]) -> DynamicImage {
+    let (height, width) = channels[0].dim();
+    let value = |c: usize, x: u32, y: u32| (channels[c][[y as usize, x as usize]].clamp(0.0, 1.0) * 255.0).round() as u8;
+    if channels.len() == 1 {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(width as u32, height as u32, |x, y| Luma([value(0, x, y)])))
+    } else {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width as u32, height as u32, |x, y| {
+            Rgb([value(0, x, y), value(1, x, y), value(2, x, y)])
+        }))
+    }
+}
+
+/// Replaces every amplitude with `target`, keeping the phase.
+fn impose_amplitude(spectrum: &mut [Complex64], target: &[f64]) {
+    for (coefficient, &amplitude) in spectrum.iter_mut().zip(target) {
+        *coefficient = Complex64::from_polar(amplitude, coefficient.arg());
+    }
+}
+
+/// Scales every ring so that its average amplitude becomes `target`, keeping the phase and the
+/// relative amplitudes within the ring.
+fn impose_rotational_average(spectrum: &mut [Complex64], target: &[f64], rings: &[usize]) {
+    let amplitude: Vec<f64> = spectrum.iter().map(|c| c.norm()).collect();
+    let average = rotational_average(&amplitude, rings, target.len());
+    for (coefficient, &ring) in spectrum.iter_mut().zip(rings) {
+        if average[ring] > 0.0 {
+            *coefficient *= target[ring] / average[ring];
+        }
+    }
+}
@@ -1,12 +1,54 @@
 use std::path::PathBuf;
 use serde::{Serialize, Deserialize};
-use crate::scramble::ScrambleOptions;
+use crate::scramble::{NormalizationOptions, ScrambleOptions};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BatchProcessingOptions {
     pub input_dir: PathBuf,
     pub output_dir: PathBuf,
     pub scramble_options: ScrambleOptions,
+    /// How each image's seed is chosen. `None` behaves like `SeedStrategy::Same`.
+    pub seed_strategy: Option<SeedStrategy>,
+    /// Normalizes every scrambled image towards the average of the whole set once all images
+    /// are scrambled. Here "reference" targets in `NormalizationOptions` mean the set average.
+    /// Every image is scrambled twice: once for the statistics and once to be normalized and saved.
+    pub normalization: Option<NormalizationOptions>,
+    /// Where to write the spectrum inspection (see `SpectrumInspection::save`) of the input and
+    /// the final output of every image scrambled with `ScrambleType::Fourier`, as
+    /// `<name>_input_*` and `<name>_output_*`.
+    pub spectrum_output_dir: Option<PathBuf>,
+}
+
+/// How seeds are assigned to the images of a batch.
+///
+/// With `Same` and no `scramble_options.seed`, every image draws a fresh random seed of its
+/// own. For `FileName` and `Index`, `scramble_options.seed` is the base seed; if it is None a
+/// random base seed is drawn once per run. Each image's effective seed is recorded in its
+/// manifest.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum SeedStrategy {
+    Same,      // Every image uses `scramble_options.seed`
+    FileName,  // Derived from the base seed and a hash of the file name
+    Index,     // Derived from the base seed and the position in the file-name-sorted listing
+}
+
+/// Gives every image in a directory the same amplitude spectrum while keeping each image's
+/// phase, like SHINE's `specMatch` and `sfMatch`. All images must have the same size.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SpectrumMatchOptions {
+    pub input_dir: PathBuf,
+    pub output_dir: PathBuf,
+    pub mode: SpectrumMatchMode,
+    /// Convert images to grayscale first; otherwise each RGB channel is matched separately
+    pub grayscale: bool,
+    /// Where to save the target (mean) amplitude spectrum: raw values for `.json`, otherwise a log-scaled image
+    pub spectrum_output: Option<PathBuf>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum SpectrumMatchMode {
+    Full,        // Impose the mean 2D amplitude spectrum (specMatch)
+    Rotational,  // Impose the mean rotational average, keeping each image's orientation content (sfMatch)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -2,10 +2,12 @@ pub mod scramble;
 pub mod utils;
 pub mod batch;
 pub mod video;
+pub mod manifest;
 
 pub use scramble::*;
 pub use utils::*;
 pub use batch::*;
 pub use video::*;
+pub use manifest::*;
 
 pub type Result<T> = std::result::Result<T, anyhow::Error>;
\ No newline at end of file
@@ -0,0 +1,142 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use anyhow::Context;
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use face_detection::FaceRegion;
+use crate::Result;
+use crate::scramble::{resolve_step_seeds, NormalizationOptions, ScrambleOptions, ScrambleType, TemporalCoherenceOptions};
+
+/// Version of the engine crate that produced an output.
+pub const ENGINE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// A detected face region as recorded in a manifest.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RegionRecord {
+    pub x1: u32,
+    pub y1: u32,
+    pub x2: u32,
+    pub y2: u32,
+    pub confidence: f32,
+}
+
+impl From<&RegionRecord> for FaceRegion {
+    fn from(record: &RegionRecord) -> Self {
+        Self {
+            x1: record.x1,
+            y1: record.y1,
+            x2: record.x2,
+            y2: record.y2,
+            confidence: record.confidence,
+        }
+    }
+}
+
+impl From<&FaceRegion> for RegionRecord {
+    fn from(region: &FaceRegion) -> Self {
+        Self {
+            x1: region.x1,
+            y1: region.y1,
+            x2: region.x2,
+            y2: region.y2,
+            confidence: region.confidence,
+        }
+    }
+}
+
+/// Sidecar written next to every scrambled image, holding everything needed to regenerate it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImageManifest {
+    pub engine_version: String,
+    pub input_path: PathBuf,
+    pub input_sha256: String,
+    pub output_path: PathBuf,
+    /// The options used, with `seed` replaced by the effective seed and random pipeline step
+    /// seeds by the seeds they drew
+    pub scramble_options: ScrambleOptions,
+    pub effective_seed: u64,
+    pub face_regions: Vec<RegionRecord>,
+    /// Set-level normalization applied after scrambling, see `BatchProcessingOptions::normalization`
+    pub batch_normalization: Option<NormalizationOptions>,
+}
+
+/// Face regions detected on one video frame.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FrameRegions {
+    pub frame_index: usize,
+    pub regions: Vec<RegionRecord>,
+}
+
+/// Sidecar written next to every scrambled video.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VideoManifest {
+    pub engine_version: String,
+    pub input_path: PathBuf,
+    pub input_sha256: String,
+    pub output_path: PathBuf,
+    pub scramble_options: ScrambleOptions,
+    pub effective_seed: u64,
+    /// When true, frame N was scrambled with `derive_seed(effective_seed, N)` instead of `effective_seed`
+    pub per_frame_seeds: bool,
+    /// Pipeline steps, indexed depth first with nested pipelines included, whose `StepSeed::Fixed`
+    /// in `scramble_options` was drawn for a `StepSeed::Random`. Frame N used
+    /// `derive_seed(seed, N)` for these steps, so they differ from frame to frame.
+    /// `None` when no step seed was random.
+    pub random_step_seeds: Option<Vec<usize>>,
+    pub temporal_coherence: Option<TemporalCoherenceOptions>,
+    /// Only frames on which faces were detected are listed
+    pub face_regions: Vec<FrameRegions>,
+}
+
+/// Returns `options` with a concrete seed, drawing a random one when `seed` is `None`,
+/// together with that seed. Random pipeline step seeds are drawn as well (see
+/// `resolve_step_seeds`), so the returned options replay the same output.
+pub fn resolve_seed(options: &ScrambleOptions) -> (ScrambleOptions, u64) {
+    let (resolved, seed, _) = resolve_seed_with_steps(options);
+    (resolved, seed)
+}
+
+/// Same as `resolve_seed`, but also returns the indices of the pipeline steps whose random
+/// seed was drawn, as returned by `resolve_step_seeds`.
+pub fn resolve_seed_with_steps(options: &ScrambleOptions) -> (ScrambleOptions, u64, Vec<usize>) {
+    let seed = options.seed.unwrap_or_else(rand::random);
+    let mut resolved = options.clone();
+    resolved.seed = Some(seed);
+    let random_steps = match &mut resolved.scramble_type {
+        ScrambleType::Pipeline(steps) => resolve_step_seeds(steps),
+        _ => Vec::new(),
+    };
+    (resolved, seed, random_steps)
+}
+
+/// SHA-256 of a file's contents as a lowercase hex string.
+pub fn hash_file(path: &Path) -> Result<String> {
+    let mut file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open file for hashing: {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Path of the sidecar for `output_path`, e.g. `face.png` -> `face.png.json`.
+pub fn sidecar_path(output_path: &Path) -> PathBuf {
+    let mut name = output_path.as_os_str().to_owned();
+    name.push(".json");
+    PathBuf::from(name)
+}
+
+/// Writes `manifest` as pretty-printed JSON next to `output_path` and returns the sidecar path.
+pub fn write_sidecar<T: Serialize>(manifest: &T, output_path: &Path) -> Result<PathBuf> {
+    let path = sidecar_path(output_path);
+    let json = serde_json::to_string_pretty(manifest)?;
+    std::fs::write(&path, json)
+        .with_context(|| format!("Failed to write manifest: {}", path.display()))?;
+    Ok(path)
+}
@@ -0,0 +1,222 @@
+use image::{DynamicImage, GenericImageView, Rgb, RgbImage, Rgba, RgbaImage};
+use super::scrambler::Scrambler;
+use super::types::{ColorSpace, ColorSpaceOptions};
+use crate::Result;
+
+/// Runs another scrambler in a different color space.
+///
+/// The image is converted to `space` and its three channels are stored, normalized to
+/// `0..=255`, in the RGB channels of the image handed to the inner scrambler. Afterwards the
+/// selected channels are taken from the scrambled image, the others from the original, and
+/// the result is converted back to sRGB.
+pub struct ColorSpaceScrambler {
+    inner: Box<dyn Scrambler>,
+    options: ColorSpaceOptions,
+}
+
+impl ColorSpaceScrambler {
+    pub fn new(inner: Box<dyn Scrambler>, options: ColorSpaceOptions) -> Self {
+        Self { inner, options }
+    }
+}
+
+impl Scrambler for ColorSpaceScrambler {
+    fn scramble(&mut self, image: &DynamicImage) -> Result<DynamicImage> {
+        let space = &self.options.space;
+        let rgba = image.to_rgba8();
+        let (width, height) = rgba.dimensions();
+        let original: Vec<[f32; 3]> = rgba
+            .pixels()
+            .map(|pixel| to_color_space([pixel[0], pixel[1], pixel[2]].map(|c| c as f32 / 255.0), space))
+            .collect();
+        let mut encoded = RgbaImage::new(width, height);
+        for ((pixel, color), source) in encoded.pixels_mut().zip(original.iter()).zip(rgba.pixels()) {
+            let [c0, c1, c2] = color.map(to_u8);
+            *pixel = Rgba([c0, c1, c2, source[3]]);
+        }
+
+        let scrambled = self.inner.scramble(&DynamicImage::ImageRgba8(encoded))?;
+        if scrambled.dimensions() != (width, height) {
+            anyhow::bail!("Scrambler changed the image size, cannot convert back from {:?}", space);
+        }
+        if !scrambled.color().has_color() {
+            anyhow::bail!("Grayscale output cannot be converted back from {:?}", space);
+        }
+        let keep_alpha = scrambled.color().has_alpha();
+        let scrambled = scrambled.to_rgba8();
+
+        let channels = self.options.channels;
+        let decoded = scrambled.pixels().zip(original.iter()).map(|(pixel, color)| {
+            let mut working = *color;
+            for ((value, &selected), &scrambled) in working.iter_mut().zip(&channels).zip(&pixel.0) {
+                if selected {
+                    *value = scrambled as f32 / 255.0;
+                }
+            }
+            (from_color_space(working, space).map(to_u8), pixel[3])
+        });
+        if keep_alpha {
+            let mut output = RgbaImage::new(width, height);
+            for (pixel, ([r, g, b], a)) in output.pixels_mut().zip(decoded) {
+                *pixel = Rgba([r, g, b, a]);
+            }
+            Ok(DynamicImage::ImageRgba8(output))
+        } else {
+            let mut output = RgbImage::new(width, height);
+            for (pixel, (rgb, _)) in output.pixels_mut().zip(decoded) {
+                *pixel = Rgb(rgb);
+            }
+            Ok(DynamicImage::ImageRgb8(output))
+        }
+    }
+}
+
+fn to_u8(value: f32) -> u8 {
+    (value * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+/// Converts an sRGB color with components in `[0, 1]` to `space`, with every component
+/// normalized to `[0, 1]`:
+/// - Lab: `L* / 100`, `(a* + 128) / 255`, `(b* + 128) / 255`
+/// - YCbCr: full-range BT.601 (as in JPEG)
+/// - Hsv: `hue / 360`, saturation, value
+pub fn to_color_space(rgb: [f32; 3], space: &ColorSpace) -> [f32; 3] {
+    match space {
+        ColorSpace::Rgb => rgb,
+        ColorSpace::Lab => {
+            let [x, y, z] = linear_to_xyz(rgb.map(srgb_to_linear));
+            let fx = lab_f(x / WHITE[0]);
+            let fy = lab_f(y / WHITE[1]);
+            let fz = lab_f(z / WHITE[2]);
+            let l = 116.0 * fy - 16.0;
+            let a = 500.0 * (fx - fy);
+            let b = 200.0 * (fy - fz);
+            [l / 100.0, (a + 128.0) / 255.0, (b + 128.0) / 255.0]
+        }
+        ColorSpace::YCbCr => {
+            let [r, g, b] = rgb;
+            [
+                0.299 * r + 0.587 * g + 0.114 * b,
+                0.5 - 0.168736 * r - 0.331264 * g + 0.5 * b,
+                0.5 + 0.5 * r - 0.418688 * g - 0.081312 * b,
+            ]
+        }
+        ColorSpace::Hsv => {
+            let [r, g, b] = rgb;
+            let max = r.max(g).max(b);
+            let min = r.min(g).min(b);
+            let delta = max - min;
+            let hue = if delta <= 0.0 {
+                0.0
+            } else if max == r {
+                ((g - b) / delta).rem_euclid(6.0)
+            } else if max == g {
+                (b - r) / delta + 2.0
+            } else {
+                (r - g) / delta + 4.0
+            };
+            let saturation = if max <= 0.0 { 0.0 } else { delta / max };
+            [hue / 6.0, saturation, max]
+        }
+    }
+}
+
+/// Inverse of `to_color_space`. The result is clamped to the sRGB gamut.
+pub fn from_color_space(color: [f32; 3], space: &ColorSpace) -> [f32; 3] {
+    let rgb = match space {
+        ColorSpace::Rgb => color,
+        ColorSpace::Lab => {
+            let l = color[0] * 100.0;
+            let a = color[1] * 255.0 - 128.0;
+            let b = color[2] * 255.0 - 128.0;
+            let fy = (l + 16.0) / 116.0;
+            let fx = fy + a / 500.0;
+            let fz = fy - b / 200.0;
+            let xyz = [
+                lab_f_inv(fx) * WHITE[0],
+                lab_f_inv(fy) * WHITE[1],
+                lab_f_inv(fz) * WHITE[2],
+            ];
+            xyz_to_linear(xyz).map(linear_to_srgb)
+        }
+        ColorSpace::YCbCr => {
+            let [y, cb, cr] = color;
+            [
+                y + 1.402 * (cr - 0.5),
+                y - 0.344136 * (cb - 0.5) - 0.714136 * (cr - 0.5),
+                y + 1.772 * (cb - 0.5),
+            ]
+        }
+        ColorSpace::Hsv => {
+            let [hue, saturation, value] = color;
+            let h = hue.rem_euclid(1.0) * 6.0;
+            let chroma = value * saturation.clamp(0.0, 1.0);
+            let x = chroma * (1.0 - ((h % 2.0) - 1.0).abs());
+            let (r, g, b) = match h as u32 {
+                0 => (chroma, x, 0.0),
+                1 => (x, chroma, 0.0),
+                2 => (0.0, chroma, x),
+                3 => (0.0, x, chroma),
+                4 => (x, 0.0, chroma),
+                _ => (chroma, 0.0, x),
+            };
+            let m = value - chroma;
+            [r + m, g + m, b + m]
+        }
+    };
+    rgb.map(|c| c.clamp(0.0, 1.0))
+}
+
+/// D65 reference white in XYZ.
+const WHITE: [f32; 3] = [0.95047, 1.0, 1.08883];
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    let c = c.clamp(0.0, 1.0);
+    if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn linear_to_xyz([r, g, b]: [f32; 3]) -> [f32; 3] {
+    [
+        0.4124564 * r + 0.3575761 * g + 0.1804375 * b,
+        0.2126729 * r + 0.7151522 * g + 0.072175 * b,
+        0.0193339 * r + 0.119192 * g + 0.9503041 * b,
+    ]
+}
+
+fn xyz_to_linear([x, y, z]: [f32; 3]) -> [f32; 3] {
+    [
+        3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+        -0.969266 * x + 1.8760108 * y + 0.041556 * z,
+        0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+    ]
+}
+
+const LAB_DELTA: f32 = 6.0 / 29.0;
+
+fn lab_f(t: f32) -> f32 {
+    if t > LAB_DELTA * LAB_DELTA * LAB_DELTA {
+        t.cbrt()
+    } else {
+        t / (3.0 * LAB_DELTA * LAB_DELTA) + 4.0 / 29.0
+    }
+}
+
+fn lab_f_inv(t: f32) -> f32 {
+    if t > LAB_DELTA {
+        t * t * t
+    } else {
+        3.0 * LAB_DELTA * LAB_DELTA * (t - 4.0 / 29.0)
+    }
+}
@@ -1,13 +1,12 @@
 // Diffeomorphic scrambling based on Stojanoski & Cusack (2014).
 // Code adapted from the original MATLAB implementation https://github.com/rhodricusack/diffeomorph/, MIT License.
 
-use image::{DynamicImage, GenericImageView, RgbaImage}; 
-use face_detection::{detect_face_regions, load_face_detector};
+use image::{DynamicImage, RgbaImage};
 use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
-use super::types::{DiffeomorphicOptions, BackgroundMode};
+use super::types::DiffeomorphicOptions;
+use super::scrambler::Scrambler;
 use crate::Result;
-use crate::FaceDetectionOptions;
 
 pub struct DiffeomorphicScrambler {
     options: DiffeomorphicOptions,
@@ -23,77 +22,6 @@ impl DiffeomorphicScrambler {
         Self { options, rng }
     }
 
-    pub fn scramble(&mut self, image: &DynamicImage) -> Result<DynamicImage> {
-        let rgba = image.to_rgba8();
-        let (width, height) = rgba.dimensions();
-
-        let (warp_x, warp_y) = self.make_warp_field(width as usize, height as usize);
-        let result = apply_warp(&rgba, &warp_x, &warp_y);
-
-        Ok(DynamicImage::ImageRgba8(result))
-    }
-
-    pub fn scramble_with_face_detection(
-        &mut self,
-        image: &DynamicImage,
-        face_opts: &FaceDetectionOptions,
-    ) -> Result<DynamicImage> {
-        let session = load_face_detector(None)?;
-        let face_regions = detect_face_regions(
-            image,
-            session,
-            face_opts.confidence_threshold,
-            Some(face_opts.expansion_factor),
-        )?;
-
-        let (width, height) = image.dimensions();
-
-        match face_opts.background_mode {
-            BackgroundMode::Include => {
-                let mut result = image.to_rgba8();
-                for region in face_regions {
-                    let rw = region.x2 - region.x1;
-                    let rh = region.y2 - region.y1;
-                    let sub = image.crop_imm(region.x1, region.y1, rw, rh);
-                    let sub_rgba = sub.to_rgba8();
-
-                    let (wx, wy) = self.make_warp_field(rw as usize, rh as usize);
-                    let warped = apply_warp(&sub_rgba, &wx, &wy);
-
-                    for y in 0..rh {
-                        for x in 0..rw {
-                            if x + region.x1 < width && y + region.y1 < height {
-                                result.put_pixel(x + region.x1, y + region.y1, *warped.get_pixel(x, y));
-                            }
-                        }
-                    }
-                }
-                Ok(DynamicImage::ImageRgba8(result))
-            }
-            BackgroundMode::Exclude => {
-                let mut result = RgbaImage::new(width, height);
-                for region in face_regions {
-                    let rw = region.x2 - region.x1;
-                    let rh = region.y2 - region.y1;
-                    let sub = image.crop_imm(region.x1, region.y1, rw, rh);
-                    let sub_rgba = sub.to_rgba8();
-
-                    let (wx, wy) = self.make_warp_field(rw as usize, rh as usize);
-                    let warped = apply_warp(&sub_rgba, &wx, &wy);
-
-                    for y in 0..rh {
-                        for x in 0..rw {
-                            if x + region.x1 < width && y + region.y1 < height {
-                                result.put_pixel(x + region.x1, y + region.y1, *warped.get_pixel(x, y));
-                            }
-                        }
-                    }
-                }
-                Ok(DynamicImage::ImageRgba8(result))
-            }
-        }
-    }
-
     /// Generate a diffeomorphic warp field using random DCT components.
     /// Returns (warp_x, warp_y) absolute coordinate fields, each of size width*height.
     /// Matches the getdiffeo function from Stojanoski & Cusack (2014).
@@ -113,8 +41,8 @@ impl DiffeomorphicScrambler {
 
         for i in 0..n {
             for j in 0..n {
-                for k in 0..4 {
-                    ph[i][j][k] = self.rng.random::<f32>() * two_pi;
+                for phase in ph[i][j].iter_mut() {
+                    *phase = self.rng.random::<f32>() * two_pi;
                 }
                 amp_x[i][j] = self.rng.random::<f32>() * two_pi;
                 amp_y[i][j] = self.rng.random::<f32>() * two_pi;
@@ -208,6 +136,18 @@ impl DiffeomorphicScrambler {
     }
 }
 
+impl Scrambler for DiffeomorphicScrambler {
+    fn scramble(&mut self, image: &DynamicImage) -> Result<DynamicImage> {
+        let rgba = image.to_rgba8();
+        let (width, height) = rgba.dimensions();
+
+        let (warp_x, warp_y) = self.make_warp_field(width as usize, height as usize);
+        let result = apply_warp(&rgba, &warp_x, &warp_y);
+
+        Ok(DynamicImage::ImageRgba8(result))
+    }
+}
+
 /// Sample the warp field at a fractional position using bilinear interpolation.
 fn sample_warp_bilinear(
     warp_x: &[f32], warp_y: &[f32],
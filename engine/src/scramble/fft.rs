@@ -0,0 +1,210 @@
+use std::sync::Arc;
+use ndarray::Array2;
+use num_complex::Complex64;
+use rayon::prelude::*;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+use rustfft::{Fft, FftPlanner};
+use crate::Result;
+
+/// 2D FFT of real images. Rows use a real-input FFT (only `width / 2 + 1` bins are computed),
+/// columns a complex FFT, and both passes run on the rayon thread pool.
+///
+/// `forward` returns the full `height x width` spectrum, with the redundant half filled in
+/// from conjugate symmetry, so callers can index it exactly like the output of a complex FFT.
+/// `inverse` expects a conjugate-symmetric spectrum and only reads its non-redundant half.
+pub(crate) struct Fft2d {
+    real_planner: RealFftPlanner<f64>,
+    planner: FftPlanner<f64>,
+    r2c: Arc<dyn RealToComplex<f64>>,
+    c2r: Arc<dyn ComplexToReal<f64>>,
+    col_fft: Arc<dyn Fft<f64>>,
+    col_ifft: Arc<dyn Fft<f64>>,
+}
+
+impl Fft2d {
+    pub fn new(height: usize, width: usize) -> Self {
+        let mut real_planner = RealFftPlanner::new();
+        let mut planner = FftPlanner::new();
+        let r2c = real_planner.plan_fft_forward(width);
+        let c2r = real_planner.plan_fft_inverse(width);
+        let col_fft = planner.plan_fft_forward(height);
+        let col_ifft = planner.plan_fft_inverse(height);
+        Self {
+            real_planner,
+            planner,
+            r2c,
+            c2r,
+            col_fft,
+            col_ifft,
+        }
+    }
+
+    /// `(height, width)` of the transforms currently planned.
+    pub fn dim(&self) -> (usize, usize) {
+        (self.col_fft.len(), self.r2c.len())
+    }
+
+    /// Re-plans the transforms for another size. Plans are cached by the planners,
+    /// so switching back and forth between sizes is cheap.
+    pub fn ensure_size(&mut self, height: usize, width: usize) {
+        if self.r2c.len() != width {
+            self.r2c = self.real_planner.plan_fft_forward(width);
+            self.c2r = self.real_planner.plan_fft_inverse(width);
+        }
+        if self.col_fft.len() != height {
+            self.col_fft = self.planner.plan_fft_forward(height);
+            self.col_ifft = self.planner.plan_fft_inverse(height);
+        }
+    }
+
+    pub fn forward(&self, input: &Array2<f64>) -> Result<Vec<Complex64>> {
+        let (h, w) = self.dim();
+        if input.dim() != (h, w) {
+            anyhow::bail!("FFT planned for {}x{} but got {}x{}", w, h, input.dim().1, input.dim().0);
+        }
+        let half = w / 2 + 1;
+
+        let mut rows: Vec<f64> = input.iter().copied().collect();
+        let mut half_spectrum = vec![Complex64::new(0.0, 0.0); h * half];
+        let r2c = &self.r2c;
+        rows.par_chunks_mut(w)
+            .zip(half_spectrum.par_chunks_mut(half))
+            .try_for_each_init(
+                || r2c.make_scratch_vec(),
+                |scratch, (row, out)| r2c.process_with_scratch(row, out, scratch),
+            )?;
+
+        // Columns are transformed in column-major order so every column is contiguous.
+        let col_fft = &self.col_fft;
+        let mut columns = vec![Complex64::new(0.0, 0.0); half * h];
+        columns.par_chunks_mut(h).enumerate().for_each_init(
+            || vec![Complex64::new(0.0, 0.0); col_fft.get_inplace_scratch_len()],
+            |scratch, (x, column)| {
+                for (y, val) in column.iter_mut().enumerate() {
+                    *val = half_spectrum[y * half + x];
+                }
+                col_fft.process_with_scratch(column, scratch);
+            },
+        );
+
+        let mut spectrum = vec![Complex64::new(0.0, 0.0); h * w];
+        spectrum.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+            for (x, val) in row.iter_mut().enumerate() {
+                *val = if x < half {
+                    columns[x * h + y]
+                } else {
+                    columns[(w - x) * h + (h - y) % h].conj()
+                };
+            }
+        });
+        Ok(spectrum)
+    }
+
+    /// Inverse transform, normalized so that `inverse(forward(x)) == x`.
+    pub fn inverse(&self, spectrum: &[Complex64]) -> Result<Array2<f64>> {
+        let (h, w) = self.dim();
+        if spectrum.len() != h * w {
+            anyhow::bail!("FFT planned for {}x{} but got {} coefficients", w, h, spectrum.len());
+        }
+        let half = w / 2 + 1;
+
+        let col_ifft = &self.col_ifft;
+        let mut columns = vec![Complex64::new(0.0, 0.0); half * h];
+        columns.par_chunks_mut(h).enumerate().for_each_init(
+            || vec![Complex64::new(0.0, 0.0); col_ifft.get_inplace_scratch_len()],
+            |scratch, (x, column)| {
+                for (y, val) in column.iter_mut().enumerate() {
+                    *val = spectrum[y * w + x];
+                }
+                col_ifft.process_with_scratch(column, scratch);
+            },
+        );
+
+        let mut half_spectrum = vec![Complex64::new(0.0, 0.0); h * half];
+        half_spectrum.par_chunks_mut(half).enumerate().for_each(|(y, row)| {
+            for (x, val) in row.iter_mut().enumerate() {
+                *val = columns[x * h + y];
+            }
+            // The DC and Nyquist bins of a real row are real; drop rounding noise so the
+            // complex-to-real transform accepts them.
+            row[0].im = 0.0;
+            if w % 2 == 0 {
+                row[half - 1].im = 0.0;
+            }
+        });
+
+        let c2r = &self.c2r;
+        let mut rows = vec![0.0; h * w];
+        half_spectrum.par_chunks_mut(half)
+            .zip(rows.par_chunks_mut(w))
+            .try_for_each_init(
+                || c2r.make_scratch_vec(),
+                |scratch, (row, out)| c2r.process_with_scratch(row, out, scratch),
+            )?;
+
+        let scale = 1.0 / (h * w) as f64;
+        rows.par_iter_mut().for_each(|val| *val *= scale);
+        Ok(Array2::from_shape_vec((h, w), rows)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    // Odd, prime and non-7-smooth sizes exercise every branch of the half-spectrum handling
+    const SIZES: [(usize, usize); 6] = [(1, 17), (7, 9), (11, 13), (22, 26), (26, 11), (16, 12)];
+
+    fn random_input(height: usize, width: usize) -> Array2<f64> {
+        let mut rng = StdRng::seed_from_u64((height * 1000 + width) as u64);
+        Array2::from_shape_fn((height, width), |_| rng.random::<f64>())
+    }
+
+    #[test]
+    fn inverse_restores_forward_input() {
+        for (height, width) in SIZES {
+            let fft = Fft2d::new(height, width);
+            let input = random_input(height, width);
+            let output = fft.inverse(&fft.forward(&input).unwrap()).unwrap();
+            for (a, b) in input.iter().zip(output.iter()) {
+                assert!((a - b).abs() < 1e-12, "{}x{}: {} != {}", width, height, a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn forward_is_conjugate_symmetric() {
+        for (height, width) in SIZES {
+            let spectrum = Fft2d::new(height, width).forward(&random_input(height, width)).unwrap();
+            for y in 0..height {
+                for x in 0..width {
+                    let mirrored = spectrum[((height - y) % height) * width + (width - x) % width];
+                    let error = (spectrum[y * width + x] - mirrored.conj()).norm();
+                    assert!(error < 1e-9, "{}x{} at ({}, {})", width, height, x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn forward_matches_direct_dft() {
+        for (height, width) in SIZES {
+            let input = random_input(height, width);
+            let spectrum = Fft2d::new(height, width).forward(&input).unwrap();
+            for v in 0..height {
+                for u in 0..width {
+                    let mut expected = Complex64::new(0.0, 0.0);
+                    for ((y, x), &value) in input.indexed_iter() {
+                        let angle = -2.0 * std::f64::consts::PI
+                            * ((u * x) as f64 / width as f64 + (v * y) as f64 / height as f64);
+                        expected += Complex64::from_polar(value, angle);
+                    }
+                    let error = (spectrum[v * width + u] - expected).norm();
+                    assert!(error < 1e-9, "{}x{} at ({}, {})", width, height, u, v);
+                }
+            }
+        }
+    }
+}
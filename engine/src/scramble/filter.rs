@@ -0,0 +1,251 @@
+use super::types::{FilterOptions, FilterShape, FrequencyRange, FrequencyUnits, OrientationOptions};
+
+/// Radial frequency of every coefficient of a padded spectrum, in the requested units.
+pub(crate) struct FrequencyGrid {
+    height: usize,
+    width: usize,
+    /// Multiplies cycles per pixel to get the requested units; `None` for `FrequencyUnits::Nyquist`.
+    scale: Option<f64>,
+}
+
+impl FrequencyGrid {
+    /// `height` and `width` are the padded spectrum size, `image_dim` the `(height, width)`
+    /// of the image before padding.
+    pub fn new(height: usize, width: usize, image_dim: (usize, usize), units: &FrequencyUnits) -> Self {
+        let scale = match units {
+            FrequencyUnits::Nyquist => None,
+            FrequencyUnits::CyclesPerImage => Some(image_dim.0.max(image_dim.1) as f64),
+            FrequencyUnits::CyclesPerDegree { pixels_per_degree } => Some(*pixels_per_degree as f64),
+        };
+        Self { height, width, scale }
+    }
+
+    pub fn radius(&self, y: usize, x: usize) -> f64 {
+        let (h, w) = (self.height, self.width);
+        match self.scale {
+            None => normalized_radius(y, x, h, w),
+            Some(scale) => {
+                let fy = y.min(h - y) as f64 / h as f64;
+                let fx = x.min(w - x) as f64 / w as f64;
+                (fy * fy + fx * fx).sqrt() * scale
+            }
+        }
+    }
+}
+
+/// Radial frequency of coefficient `(y, x)`, with each axis measured as a fraction of its
+/// Nyquist frequency (1.0 on the axes at Nyquist, up to about 1.41 in the corners).
+pub(crate) fn normalized_radius(y: usize, x: usize, h: usize, w: usize) -> f64 {
+    let fy = y.min(h - y) as f64 / (h as f64 / 2.0);
+    let fx = x.min(w - x) as f64 / (w as f64 / 2.0);
+    (fy * fy + fx * fx).sqrt()
+}
+
+/// Ring (radial frequency in the units of `grid`, rounded) of every coefficient.
+pub(crate) fn ring_indices(grid: &FrequencyGrid, height: usize, width: usize) -> Vec<usize> {
+    let mut rings = Vec::with_capacity(height * width);
+    for y in 0..height {
+        for x in 0..width {
+            rings.push(grid.radius(y, x).round() as usize);
+        }
+    }
+    rings
+}
+
+pub(crate) fn rotational_average(amplitude: &[f64], rings: &[usize], ring_count: usize) -> Vec<f64> {
+    let mut sum = vec![0.0; ring_count];
+    let mut count = vec![0usize; ring_count];
+    for (&value, &ring) in amplitude.iter().zip(rings) {
+        sum[ring] += value;
+        count[ring] += 1;
+    }
+    sum.iter()
+        .zip(&count)
+        .map(|(&sum, &count)| if count > 0 { sum / count as f64 } else { 0.0 })
+        .collect()
+}
+
+/// Gain of the `range` filter at radial frequency `f`, in `[0, 1]`.
+pub(crate) fn filter_gain(range: &FrequencyRange, filter: &FilterOptions, f: f64) -> f64 {
+    let shape = &filter.shape;
+    match range {
+        FrequencyRange::All => 1.0,
+        FrequencyRange::LowPass(cutoff) => low_pass(shape, f, *cutoff as f64),
+        FrequencyRange::HighPass(cutoff) => high_pass(shape, f, *cutoff as f64),
+        FrequencyRange::BandPass { low, high } => {
+            low_pass(shape, f, *high as f64) * high_pass(shape, f, *low as f64)
+        }
+    }
+}
+
+fn low_pass(shape: &FilterShape, f: f64, cutoff: f64) -> f64 {
+    match shape {
+        FilterShape::Ideal => if f <= cutoff { 1.0 } else { 0.0 },
+        FilterShape::Butterworth(order) => {
+            if cutoff <= 0.0 {
+                return if f <= 0.0 { 1.0 } else { 0.0 };
+            }
+            1.0 / (1.0 + (f / cutoff).powi(2 * (*order).max(1) as i32))
+        }
+        FilterShape::Gaussian => {
+            if cutoff <= 0.0 {
+                return if f <= 0.0 { 1.0 } else { 0.0 };
+            }
+            (-(f * f) / (2.0 * cutoff * cutoff)).exp()
+        }
+        FilterShape::RaisedCosine(width) => {
+            let width = *width as f64;
+            let start = cutoff - width / 2.0;
+            if f <= start {
+                1.0
+            } else if f >= cutoff + width / 2.0 {
+                0.0
+            } else {
+                0.5 * (1.0 + (std::f64::consts::PI * (f - start) / width).cos())
+            }
+        }
+    }
+}
+
+fn high_pass(shape: &FilterShape, f: f64, cutoff: f64) -> f64 {
+    match shape {
+        // Keeps the cutoff frequency itself, like the low-pass does
+        FilterShape::Ideal => if f >= cutoff { 1.0 } else { 0.0 },
+        _ => 1.0 - low_pass(shape, f, cutoff),
+    }
+}
+
+/// Gain of the orientation band at coefficient `(y, x)` of an `h x w` spectrum, in `[0, 1]`.
+/// The DC term has no orientation and always passes. A Nyquist frequency is the same for both
+/// signs, so its coefficients get the average gain of the two, which keeps the gain
+/// conjugate-symmetric.
+pub(crate) fn orientation_gain(options: &OrientationOptions, y: usize, x: usize, h: usize, w: usize) -> f64 {
+    if y == 0 && x == 0 {
+        return 1.0;
+    }
+    // Signed frequencies in cycles per pixel, with the vertical axis pointing up
+    let fy = -(if y <= h / 2 { y as f64 } else { y as f64 - h as f64 }) / h as f64;
+    let fx = (if x <= w / 2 { x as f64 } else { x as f64 - w as f64 }) / w as f64;
+    let y_signs: &[f64] = if h.is_multiple_of(2) && y == h / 2 { &[1.0, -1.0] } else { &[1.0] };
+    let x_signs: &[f64] = if w.is_multiple_of(2) && x == w / 2 { &[1.0, -1.0] } else { &[1.0] };
+    let mut sum = 0.0;
+    for sign_y in y_signs {
+        for sign_x in x_signs {
+            sum += oriented_gain(options, sign_y * fy, sign_x * fx);
+        }
+    }
+    sum / (y_signs.len() * x_signs.len()) as f64
+}
+
+/// Gain of the orientation band for the frequency vector `(fy, fx)`.
+fn oriented_gain(options: &OrientationOptions, fy: f64, fx: f64) -> f64 {
+    // Edges run perpendicular to their frequency vector
+    let orientation = fy.atan2(fx).to_degrees() + 90.0;
+    let mut distance = (orientation - options.center as f64).rem_euclid(180.0);
+    if distance > 90.0 {
+        distance = 180.0 - distance;
+    }
+    let half_width = options.half_width as f64;
+    let transition = options.transition as f64;
+    if distance <= half_width {
+        1.0
+    } else if transition <= 0.0 || distance >= half_width + transition {
+        0.0
+    } else {
+        0.5 * (1.0 + (std::f64::consts::PI * (distance - half_width) / transition).cos())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scramble::OrientationTarget;
+
+    fn gain(range: FrequencyRange, shape: FilterShape, f: f64) -> f64 {
+        filter_gain(&range, &FilterOptions { shape, units: FrequencyUnits::Nyquist }, f)
+    }
+
+    #[test]
+    fn shapes_have_their_documented_gain_at_the_cutoff() {
+        let at_cutoff = |shape| gain(FrequencyRange::LowPass(0.5), shape, 0.5);
+        assert_eq!(at_cutoff(FilterShape::Ideal), 1.0);
+        assert!((at_cutoff(FilterShape::Butterworth(4)) - 0.5).abs() < 1e-12);
+        assert!((at_cutoff(FilterShape::Gaussian) - (-0.5f64).exp()).abs() < 1e-12);
+        assert!((at_cutoff(FilterShape::RaisedCosine(0.2)) - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn shapes_pass_low_and_stop_high_frequencies() {
+        let shapes = [FilterShape::Ideal, FilterShape::Butterworth(8), FilterShape::Gaussian, FilterShape::RaisedCosine(0.2)];
+        for shape in shapes {
+            assert!(gain(FrequencyRange::LowPass(0.5), shape.clone(), 0.0) > 0.99);
+            assert!(gain(FrequencyRange::LowPass(0.5), shape.clone(), 1.4) < 0.1);
+            // Smooth high-pass filters are the complement of the low-pass
+            let low = gain(FrequencyRange::LowPass(0.5), shape.clone(), 0.45);
+            let high = gain(FrequencyRange::HighPass(0.5), shape.clone(), 0.45);
+            if !matches!(shape, FilterShape::Ideal) {
+                assert!((low + high - 1.0).abs() < 1e-12);
+            }
+            let band = gain(FrequencyRange::BandPass { low: 0.2, high: 0.6 }, shape.clone(), 0.4);
+            let low = gain(FrequencyRange::LowPass(0.6), shape.clone(), 0.4);
+            let high = gain(FrequencyRange::HighPass(0.2), shape, 0.4);
+            assert!((band - low * high).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn raised_cosine_transition_is_centered_on_the_cutoff() {
+        let shape = || FilterShape::RaisedCosine(0.2);
+        assert_eq!(gain(FrequencyRange::LowPass(0.5), shape(), 0.39), 1.0);
+        assert_eq!(gain(FrequencyRange::LowPass(0.5), shape(), 0.61), 0.0);
+        assert!(gain(FrequencyRange::LowPass(0.5), shape(), 0.45) > 0.5);
+    }
+
+    #[test]
+    fn grid_converts_units() {
+        // 4 cycles across a 64 pixel wide image
+        let radius = |units| FrequencyGrid::new(32, 64, (32, 64), &units).radius(0, 4);
+        assert!((radius(FrequencyUnits::Nyquist) - 0.125).abs() < 1e-12);
+        assert!((radius(FrequencyUnits::CyclesPerImage) - 4.0).abs() < 1e-12);
+        assert!((radius(FrequencyUnits::CyclesPerDegree { pixels_per_degree: 32.0 }) - 2.0).abs() < 1e-12);
+        // Padding does not change the frequency in cycles per image
+        let padded = FrequencyGrid::new(64, 128, (32, 64), &FrequencyUnits::CyclesPerImage).radius(0, 8);
+        assert!((padded - 4.0).abs() < 1e-12);
+    }
+
+    fn orientation(center: f32, half_width: f32, transition: f32) -> OrientationOptions {
+        OrientationOptions { center, half_width, transition, target: OrientationTarget::Filter }
+    }
+
+    #[test]
+    fn orientation_selects_edges_perpendicular_to_frequency() {
+        let (h, w) = (16, 16);
+        // Horizontal structure lives on the vertical frequency axis
+        let horizontal = orientation(0.0, 10.0, 0.0);
+        assert_eq!(orientation_gain(&horizontal, 2, 0, h, w), 1.0);
+        assert_eq!(orientation_gain(&horizontal, 0, 2, h, w), 0.0);
+        let vertical = orientation(90.0, 10.0, 0.0);
+        assert_eq!(orientation_gain(&vertical, 2, 0, h, w), 0.0);
+        assert_eq!(orientation_gain(&vertical, 0, 2, h, w), 1.0);
+        assert_eq!(orientation_gain(&vertical, 0, 0, h, w), 1.0);
+    }
+
+    #[test]
+    fn orientation_taper_is_half_way_down_at_its_center() {
+        // (2, 2) is structure at 45 degrees, 15 degrees into the 30 degree taper
+        let gain = orientation_gain(&orientation(0.0, 30.0, 30.0), 2, 2, 16, 16);
+        assert!((gain - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn orientation_gain_is_conjugate_symmetric() {
+        let (h, w) = (12, 16);
+        let options = orientation(30.0, 20.0, 15.0);
+        for y in 0..h {
+            for x in 0..w {
+                let mirrored = orientation_gain(&options, (h - y) % h, (w - x) % w, h, w);
+                assert!((orientation_gain(&options, y, x, h, w) - mirrored).abs() < 1e-12);
+            }
+        }
+    }
+}
@@ -1,32 +1,33 @@
-use image::{DynamicImage, GenericImageView, ImageBuffer, Rgb};
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgb, Rgba};
 use ndarray::Array2;
 use num_complex::Complex64;
-use rustfft::{Fft, FftPlanner};
+use rayon::prelude::*;
 use rand::{Rng, SeedableRng};
 use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
 use crate::Result;
-use super::types::{FourierOptions, PaddingMode};
-use face_detection::{detect_face_regions, load_face_detector};
-use crate::FaceDetectionOptions;
+use super::types::{
+    AlphaPolicy, FilterOptions, FilterShape, FourierOptions, FrequencyUnits, LocalPhaseOptions, OrientationTarget,
+    PaddingMode, PhaseMode, SpectrumSwapOptions, WindowFunction,
+};
+use super::scrambler::Scrambler;
+use super::fft::Fft2d;
+use super::inspect::SpectrumInspection;
+use super::filter::{filter_gain, normalized_radius, orientation_gain, FrequencyGrid};
 use crate::FrequencyRange;
-use crate::BackgroundMode;
-use image::GenericImage;
 pub struct FourierScrambler {
     width: usize,
     height: usize,
-    fft: std::sync::Arc<dyn Fft<f64>>,
-    ifft: std::sync::Arc<dyn Fft<f64>>,
+    fft: Fft2d,
     options: FourierOptions,
     rng: StdRng,
 }
 
 impl FourierScrambler {
     pub fn new(width: usize, height: usize, options: FourierOptions, seed: Option<u64>) -> Self {
-        // Determine the padded size (square) based on the maximum dimension.
-        let padded_size = get_optimal_fft_size(width.max(height));
-        let mut planner = FftPlanner::new();
-        let fft = planner.plan_fft_forward(padded_size);
-        let ifft = planner.plan_fft_inverse(padded_size);
+        // Rows and columns are padded independently, so wide images are not padded to a square.
+        let (padded_height, padded_width) = padded_dims(height, width);
+        let fft = Fft2d::new(padded_height, padded_width);
         let rng = if let Some(seed) = seed {
             StdRng::seed_from_u64(seed)
         } else {
@@ -36,254 +37,452 @@ impl FourierScrambler {
             width,
             height,
             fft,
-            ifft,
             options,
             rng,
         }
     }
 
-    /// Scrambles a single image.
-    /// If the `grayscale` option is enabled, the image is first converted to grayscale,
-    /// processed as a single luminance channel, and returned as a grayscale image.
-    /// Otherwise, each color channel is processed separately.
-    pub fn scramble(&mut self, image: &DynamicImage) -> Result<DynamicImage> {
-        if self.options.grayscale {
-            let gray_image = image.to_luma8();
-            let (width, height) = gray_image.dimensions();
-            let mut channel = Array2::zeros((height as usize, width as usize));
-            for y in 0..(height as usize) {
-                for x in 0..(width as usize) {
-                    channel[[y, x]] = gray_image.get_pixel(x as u32, y as u32)[0] as f64 / 255.0;
+    /// Processes a single channel: pads the image, computes its 2D FFT,
+    /// scrambles its magnitude and/or phase, then computes the inverse FFT.
+    fn process_channel(&mut self, channel: Array2<f64>) -> Result<Array2<f64>> {
+        let mut processed = self.process_channels(vec![channel])?;
+        Ok(processed.remove(0))
+    }
+
+    /// Processes channels of the same size. The transforms of all channels run in parallel;
+    /// the spectra are scrambled one channel after another, so seeded output does not
+    /// depend on thread scheduling.
+    fn process_channels(&mut self, channels: Vec<Array2<f64>>) -> Result<Vec<Array2<f64>>> {
+        let Some(first) = channels.first() else {
+            return Ok(Vec::new());
+        };
+        if let Some(local) = self.options.local.clone() {
+            return self.process_channels_local(channels, &local);
+        }
+        let dim = first.dim();
+        let (padded, means): (Vec<_>, Vec<_>) = channels
+            .iter()
+            .map(|channel| self.window_and_pad(channel))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .unzip();
+        let (h, w) = padded[0].dim();
+        self.fft.ensure_size(h, w);
+        let mut spectra = padded
+            .par_iter()
+            .map(|channel| self.fft.forward(channel))
+            .collect::<Result<Vec<_>>>()?;
+        let shared_phase = if self.options.phase_scramble
+            && spectra.len() > 1
+            && self.options.phase_mode == Some(PhaseMode::Shared)
+        {
+            Some(random_phase_field(&mut self.rng, h, w))
+        } else {
+            None
+        };
+        let weights = self.scramble_weights(h, w, dim);
+        for spectrum in spectra.iter_mut() {
+            self.scramble_spectrum(spectrum, (h, w), dim, shared_phase.as_deref(), weights.as_deref());
+        }
+        spectra
+            .par_iter()
+            .zip(&means)
+            .map(|(spectrum, mean)| {
+                let mut result = self.remove_padding(&self.fft.inverse(spectrum)?, dim)?;
+                if let Some(mean) = mean {
+                    restore_mean(&mut result, *mean);
+                }
+                for val in result.iter_mut() {
+                    *val = val.clamp(0.0, 1.0);
+                }
+                Ok(result)
+            })
+            .collect()
+    }
+
+    /// Local version of `process_channels`, see `LocalPhaseOptions`. Windows are processed one
+    /// row at a time: their transforms run in parallel, the scrambling in raster order.
+    fn process_channels_local(
+        &mut self,
+        channels: Vec<Array2<f64>>,
+        local: &LocalPhaseOptions,
+    ) -> Result<Vec<Array2<f64>>> {
+        let (height, width) = channels[0].dim();
+        let size = (local.window_size.max(2) as usize).next_multiple_of(2);
+        let hop = size / 2;
+        // Every pixel of the image is covered by two windows along each axis
+        let extended_h = (height + hop).next_multiple_of(hop) + hop;
+        let extended_w = (width + hop).next_multiple_of(hop) + hop;
+        let extended: Vec<Array2<f64>> = channels
+            .iter()
+            .map(|channel| {
+                Array2::from_shape_fn((extended_h, extended_w), |(y, x)| {
+                    let src_y = reflect_index((y as isize - hop as isize).unsigned_abs(), height);
+                    let src_x = reflect_index((x as isize - hop as isize).unsigned_abs(), width);
+                    channel[[src_y, src_x]]
+                })
+            })
+            .collect();
+
+        // Square root of a periodic Hann window: applied before and after the transform, the
+        // windows of a half-overlapping grid sum to one
+        let taper: Vec<f64> = (0..size)
+            .map(|n| (std::f64::consts::PI * n as f64 / size as f64).sin())
+            .collect();
+        self.fft.ensure_size(size, size);
+        let weights = self.scramble_weights(size, size, (size, size));
+        let shared = self.options.phase_scramble
+            && channels.len() > 1
+            && self.options.phase_mode == Some(PhaseMode::Shared);
+        let mut output = vec![Array2::<f64>::zeros((extended_h, extended_w)); channels.len()];
+
+        for y0 in (0..=extended_h - size).step_by(hop) {
+            let windows: Vec<(usize, usize)> = (0..=extended_w - size)
+                .step_by(hop)
+                .flat_map(|x0| (0..channels.len()).map(move |c| (x0, c)))
+                .collect();
+            let mut spectra = windows
+                .par_iter()
+                .map(|&(x0, c)| {
+                    let patch = Array2::from_shape_fn((size, size), |(y, x)| {
+                        extended[c][[y0 + y, x0 + x]] * taper[y] * taper[x]
+                    });
+                    self.fft.forward(&patch)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            for window_spectra in spectra.chunks_mut(channels.len()) {
+                let shared_phase = shared.then(|| random_phase_field(&mut self.rng, size, size));
+                for spectrum in window_spectra.iter_mut() {
+                    let dim = (size, size);
+                    self.scramble_spectrum(spectrum, dim, dim, shared_phase.as_deref(), weights.as_deref());
                 }
             }
-            let processed_channel = self.process_channel(channel)?;
-            let mut output = image::GrayImage::new(width, height);
-            for y in 0..(height as usize) {
-                for x in 0..(width as usize) {
-                    let val = (processed_channel[[y, x]] * 255.0).clamp(0.0, 255.0) as u8;
-                    output.put_pixel(x as u32, y as u32, image::Luma([val]));
+            let patches = spectra
+                .par_iter()
+                .map(|spectrum| self.fft.inverse(spectrum))
+                .collect::<Result<Vec<_>>>()?;
+            for (&(x0, c), patch) in windows.iter().zip(&patches) {
+                for y in 0..size {
+                    for x in 0..size {
+                        output[c][[y0 + y, x0 + x]] += patch[[y, x]] * taper[y] * taper[x];
+                    }
                 }
             }
-            return Ok(DynamicImage::ImageLuma8(output));
         }
 
-        let (width, height) = image.dimensions();
-        self.width = width as usize;
-        self.height = height as usize;
-        let channels = self.split_channels(image)?;
-        let processed_channels: Vec<Array2<f64>> = channels
+        Ok(output
             .into_iter()
-            .map(|channel| self.process_channel(channel))
-            .collect::<Result<Vec<_>>>()?;
-        self.combine_channels(processed_channels)
+            .map(|channel| {
+                Array2::from_shape_fn((height, width), |(y, x)| channel[[y + hop, x + hop]].clamp(0.0, 1.0))
+            })
+            .collect())
     }
-    /// Processes face regions separately.
-    /// A new scrambler is created for each region so that its FFT plan matches the region size.
-    pub fn scramble_with_face_detection(
+
+    /// Filters and scrambles one spectrum. With `shared_phase`, its phase offsets are added
+    /// instead of drawing a new random phase for this spectrum. `weights` scale how far each
+    /// coefficient is scrambled (see `scramble_weights`).
+    fn scramble_spectrum(
         &mut self,
-        image: &DynamicImage,
-        face_opts: &FaceDetectionOptions,
-    ) -> Result<DynamicImage> {
-        let session = load_face_detector(None)?;
-        let face_regions = detect_face_regions(
-            image,
-            session,
-            face_opts.confidence_threshold,
-            Some(face_opts.expansion_factor),
-        )?;
-        let (width, height) = image.dimensions();
-        let mut result = image.clone();
-        match face_opts.background_mode {
-            BackgroundMode::Include => {
-                for region in face_regions {
-                    let region_width = region.x2 - region.x1;
-                    let region_height = region.y2 - region.y1;
-                    let mut region_img = DynamicImage::new_rgb8(region_width, region_height);
-                    for y in 0..region_height {
-                        for x in 0..region_width {
-                            let pixel = image.get_pixel(x + region.x1, y + region.y1);
-                            region_img.put_pixel(x, y, pixel);
-                        }
-                    }
-                    // Create a new scrambler for the region so that FFT planning is correct.
-                    let mut region_scrambler =
-                    FourierScrambler::new(region_width as usize, region_height as usize, self.options.clone(), None);                    
-                    let processed = region_scrambler.scramble(&region_img)?;
-                    for y in 0..region_height {
-                        for x in 0..region_width {
-                            let px = processed.get_pixel(x, y);
-                            result.put_pixel(x + region.x1, y + region.y1, px);
-                        }
-                    }
+        data: &mut [Complex64],
+        (h, w): (usize, usize),
+        image_dim: (usize, usize),
+        shared_phase: Option<&[f64]>,
+        weights: Option<&[f64]>,
+    ) {
+        self.apply_frequency_filter(data, h, w, image_dim);
+        if self.options.magnitude_scramble {
+            self.magnitude_scramble(data, h, w, weights);
+        }
+        match shared_phase {
+            Some(offsets) => self.add_phase(data, offsets, h, w, weights),
+            None if self.options.phase_scramble => self.phase_scramble(data, h, w, weights),
+            None => {}
+        }
+    }
+
+    /// Per-coefficient gain of `scramble_band` and the orientation band, or `None` when every
+    /// coefficient is scrambled. The random draws do not depend on the bands, so a seed gives
+    /// the same random field with or without them.
+    fn scramble_weights(&self, h: usize, w: usize, image_dim: (usize, usize)) -> Option<Vec<f64>> {
+        let band = self.options.scramble_band.as_ref().filter(|band| !matches!(band, FrequencyRange::All));
+        let orientation = self.options.orientation.as_ref().filter(|orientation| {
+            matches!(orientation.target, OrientationTarget::Scramble | OrientationTarget::Both)
+        });
+        if band.is_none() && orientation.is_none() {
+            return None;
+        }
+        let filter = self.filter_options();
+        let grid = FrequencyGrid::new(h, w, image_dim, &filter.units);
+        let mut weights = vec![1.0; h * w];
+        for y in 0..h {
+            for x in 0..w {
+                if let Some(band) = band {
+                    weights[y * w + x] *= filter_gain(band, &filter, grid.radius(y, x));
                 }
-                Ok(result)
-            },
-            BackgroundMode::Exclude => {
-                let mut new_image = DynamicImage::new_rgb8(width, height);
-                for region in face_regions {
-                    let region_width = region.x2 - region.x1;
-                    let region_height = region.y2 - region.y1;
-                    let mut region_img = DynamicImage::new_rgb8(region_width, region_height);
-                    for y in 0..region_height {
-                        for x in 0..region_width {
-                            let pixel = image.get_pixel(x + region.x1, y + region.y1);
-                            region_img.put_pixel(x, y, pixel);
-                        }
-                    }
-                    let mut region_scrambler =
-                    FourierScrambler::new(region_width as usize, region_height as usize, self.options.clone(), None);
-                    let processed = region_scrambler.scramble(&region_img)?;
-                    for y in 0..region_height {
-                        for x in 0..region_width {
-                            let px = processed.get_pixel(x, y);
-                            new_image.put_pixel(x + region.x1, y + region.y1, px);
-                        }
-                    }
+                if let Some(orientation) = orientation {
+                    weights[y * w + x] *= orientation_gain(orientation, y, x, h, w);
                 }
-                Ok(new_image)
             }
         }
+        Some(weights)
     }
-    /// Processes a single channel: pads the image, computes its 2D FFT,
-    /// scrambles its phase, then computes the inverse FFT.
-    fn process_channel(&mut self, channel: Array2<f64>) -> Result<Array2<f64>> {
-        let padded = self.apply_padding(&channel)?;
-        let n = padded.dim().0;
-        let mut complex_data = self.to_complex(&padded);
-        self.fft2d(&mut complex_data, n);
-        self.apply_frequency_filter(&mut complex_data, n);
-        if self.options.phase_scramble {
-            self.phase_scramble(&mut complex_data);
-        }
-        self.ifft2d(&mut complex_data, n);
-        let mut result = self.remove_padding(&complex_data, channel.dim())?;
-        for val in result.iter_mut() {
-            *val = val.max(0.0).min(1.0);
-        }
-        Ok(result)
+
+    fn filter_options(&self) -> FilterOptions {
+        self.options.filter.clone().unwrap_or(FilterOptions {
+            shape: FilterShape::Ideal,
+            units: FrequencyUnits::Nyquist,
+        })
     }
 
-    fn apply_frequency_filter(&self, data: &mut [Complex64], n: usize) {
-        match &self.options.frequency_range {
-            FrequencyRange::All => {
-                // No filtering needed
-                return;
-            },
-            FrequencyRange::LowPass(cutoff) => {
-                // Keep only low frequencies (center of FFT)
-                let cutoff_radius = (cutoff * n as f32 / 2.0) as usize;
-                for y in 0..n {
-                    for x in 0..n {
-                        let center_y = if y < n/2 { y } else { n - y };
-                        let center_x = if x < n/2 { x } else { n - x };
-                        let radius = ((center_y * center_y + center_x * center_x) as f32).sqrt();
-                        
-                        if radius > cutoff_radius as f32 {
-                            data[y * n + x] = Complex64::new(0.0, 0.0);
-                        }
-                    }
-                }
-            },
-            FrequencyRange::HighPass(cutoff) => {
-                // Keep only high frequencies (edges of FFT)
-                let cutoff_radius = (cutoff * n as f32 / 2.0) as usize;
-                for y in 0..n {
-                    for x in 0..n {
-                        let center_y = if y < n/2 { y } else { n - y };
-                        let center_x = if x < n/2 { x } else { n - x };
-                        let radius = ((center_y * center_y + center_x * center_x) as f32).sqrt();
-                        
-                        if radius < cutoff_radius as f32 {
-                            data[y * n + x] = Complex64::new(0.0, 0.0);
-                        }
-                    }
+    /// Rotates every coefficient by `intensity` times its offset, keeping the spectrum
+    /// conjugate-symmetric. Applying the same offsets to every channel keeps the phase
+    /// differences between channels, and with them the colors.
+    fn add_phase(&self, data: &mut [Complex64], offsets: &[f64], h: usize, w: usize, weights: Option<&[f64]>) {
+        let intensity = self.options.intensity as f64;
+        for y in 0..h {
+            for x in 0..w {
+                let (sym_y, sym_x) = conjugate_index(y, x, h, w);
+                if y > sym_y || (y == sym_y && x >= sym_x) {
+                    continue;
                 }
-            },
-            FrequencyRange::BandPass { low, high } => {
-                let low_radius = (low * n as f32 / 2.0) as usize;
-                let high_radius = (high * n as f32 / 2.0) as usize;
-                for y in 0..n {
-                    for x in 0..n {
-                        let center_y = if y < n/2 { y } else { n - y };
-                        let center_x = if x < n/2 { x } else { n - x };
-                        let radius = ((center_y * center_y + center_x * center_x) as f32).sqrt();
-                        
-                        if radius < low_radius as f32 || radius > high_radius as f32 {
-                            data[y * n + x] = Complex64::new(0.0, 0.0);
-                        }
-                    }
+                let idx = y * w + x;
+                let weight = weights.map_or(1.0, |weights| weights[idx]);
+                if weight <= 0.0 {
+                    continue;
                 }
+                let new_val = data[idx] * Complex64::from_polar(1.0, intensity * weight * offsets[idx]);
+                data[idx] = new_val;
+                data[sym_y * w + sym_x] = new_val.conj();
             }
         }
     }
-    fn fft2d(&self, data: &mut [Complex64], n: usize) {
-        for row in 0..n {
-            let start = row * n;
-            let end = start + n;
-            self.fft.process(&mut data[start..end]);
-        }
-        let mut column = vec![Complex64::new(0.0, 0.0); n];
-        for col in 0..n {
-            for row in 0..n {
-                column[row] = data[row * n + col];
-            }
-            self.fft.process(&mut column);
-            for row in 0..n {
-                data[row * n + col] = column[row];
+
+    /// Attenuates coefficients outside the selected band and orientation. Without `filter`
+    /// options the filter is ideal and cutoffs are fractions of the Nyquist frequency along
+    /// each axis, so the band is the same for any padded aspect ratio.
+    fn apply_frequency_filter(&self, data: &mut [Complex64], h: usize, w: usize, image_dim: (usize, usize)) {
+        let Some(mask) = self.filter_mask(h, w, image_dim) else {
+            // No filtering needed
+            return;
+        };
+        for (value, &gain) in data.iter_mut().zip(&mask) {
+            if gain <= 0.0 {
+                *value = Complex64::new(0.0, 0.0);
+            } else if gain < 1.0 {
+                *value *= gain;
             }
         }
     }
 
-    fn ifft2d(&self, data: &mut [Complex64], n: usize) {
-        for row in 0..n {
-            let start = row * n;
-            let end = start + n;
-            self.ifft.process(&mut data[start..end]);
+    /// Per-coefficient gain of `frequency_range` and the orientation band, or `None` when
+    /// nothing is filtered.
+    fn filter_mask(&self, h: usize, w: usize, image_dim: (usize, usize)) -> Option<Vec<f64>> {
+        let range = &self.options.frequency_range;
+        let orientation = self.options.orientation.as_ref().filter(|orientation| {
+            matches!(orientation.target, OrientationTarget::Filter | OrientationTarget::Both)
+        });
+        if matches!(range, FrequencyRange::All) && orientation.is_none() {
+            return None;
         }
-        let mut column = vec![Complex64::new(0.0, 0.0); n];
-        for col in 0..n {
-            for row in 0..n {
-                column[row] = data[row * n + col];
-            }
-            self.ifft.process(&mut column);
-            for row in 0..n {
-                data[row * n + col] = column[row];
+        let filter = self.filter_options();
+        let grid = FrequencyGrid::new(h, w, image_dim, &filter.units);
+        let mut mask = vec![1.0; h * w];
+        for y in 0..h {
+            for x in 0..w {
+                let mut gain = filter_gain(range, &filter, grid.radius(y, x));
+                if let Some(orientation) = orientation {
+                    gain *= orientation_gain(orientation, y, x, h, w);
+                }
+                mask[y * w + x] = gain;
             }
         }
-        let scale = 1.0 / (n * n) as f64;
-        for val in data.iter_mut() {
-            *val = *val * scale;
-        }
+        Some(mask)
     }
 
-    fn phase_scramble(&mut self, data: &mut [Complex64]) {
-        let n = (data.len() as f64).sqrt() as usize;
-        for y in 0..n {
-            for x in 0..n {
-                let sym_y = if y == 0 { 0 } else { n - y };
-                let sym_x = if x == 0 { 0 } else { n - x };
+    fn phase_scramble(&mut self, data: &mut [Complex64], h: usize, w: usize, weights: Option<&[f64]>) {
+        for y in 0..h {
+            for x in 0..w {
+                let (sym_y, sym_x) = conjugate_index(y, x, h, w);
                 if y > sym_y || (y == sym_y && x > sym_x) {
                     continue;
                 }
-                let idx = y * n + x;
+                let idx = y * w + x;
                 let orig = data[idx];
                 let mag = orig.norm();
                 let orig_phase = orig.arg();
                 let random_phase = self.rng.random_range(0.0..(2.0 * std::f64::consts::PI));
+                let weight = weights.map_or(1.0, |weights| weights[idx]);
+                if weight <= 0.0 {
+                    continue;
+                }
                 let dphase = angle_difference(random_phase, orig_phase);
-                let new_phase = orig_phase + self.options.intensity as f64 * dphase;
+                let new_phase = orig_phase + self.options.intensity as f64 * weight * dphase;
                 let new_val = Complex64::from_polar(mag, new_phase);
                 data[idx] = new_val;
                 if !(y == sym_y && x == sym_x) {
-                    let sym_idx = sym_y * n + sym_x;
+                    let sym_idx = sym_y * w + sym_x;
                     data[sym_idx] = new_val.conj();
                 }
             }
         }
     }
 
-    fn to_complex(&self, real: &Array2<f64>) -> Vec<Complex64> {
-        real.iter().map(|&val| Complex64::new(val, 0.0)).collect()
+    /// Shuffles magnitudes among coefficients that share the same radial frequency band,
+    /// keeping each coefficient's phase. Only one half of the spectrum is visited and the
+    /// conjugate partner is mirrored, so the output stays real. The DC term is left untouched
+    /// to preserve mean luminance. Bands are one frequency step of the longer axis wide.
+    /// Coefficients with zero weight are left out of the shuffle.
+    fn magnitude_scramble(&mut self, data: &mut [Complex64], h: usize, w: usize, weights: Option<&[f64]>) {
+        let steps = (h.max(w) / 2) as f64;
+        let max_band = (std::f64::consts::SQRT_2 * steps).ceil() as usize;
+        let mut bands: Vec<Vec<usize>> = vec![Vec::new(); max_band + 1];
+        for y in 0..h {
+            for x in 0..w {
+                let (sym_y, sym_x) = conjugate_index(y, x, h, w);
+                if y > sym_y || (y == sym_y && x > sym_x) || (y == 0 && x == 0) {
+                    continue;
+                }
+                if weights.is_some_and(|weights| weights[y * w + x] <= 0.0) {
+                    continue;
+                }
+                let band = (normalized_radius(y, x, h, w) * steps).round() as usize;
+                bands[band.min(max_band)].push(y * w + x);
+            }
+        }
+        let intensity = self.options.intensity as f64;
+        for band in bands.iter().filter(|band| band.len() > 1) {
+            let mut shuffled: Vec<f64> = band.iter().map(|&idx| data[idx].norm()).collect();
+            shuffled.shuffle(&mut self.rng);
+            for (&idx, &new_mag) in band.iter().zip(shuffled.iter()) {
+                let orig = data[idx];
+                let weight = weights.map_or(1.0, |weights| weights[idx]);
+                let mag = orig.norm() + intensity * weight * (new_mag - orig.norm());
+                let new_val = Complex64::from_polar(mag, orig.arg());
+                data[idx] = new_val;
+                let (sym_y, sym_x) = conjugate_index(idx / w, idx % w, h, w);
+                if sym_y * w + sym_x != idx {
+                    data[sym_y * w + sym_x] = new_val.conj();
+                }
+            }
+        }
+    }
+
+    /// Spectrum of the luminance of `image` as this scrambler transforms it (windowed and
+    /// padded), with the filter and scramble-band gains it applies. Inspecting an input and its
+    /// scrambled output shows what the scrambler did. No random numbers are drawn.
+    pub fn inspect(&mut self, image: &DynamicImage) -> Result<SpectrumInspection> {
+        let (width, height) = image.dimensions();
+        self.width = width as usize;
+        self.height = height as usize;
+        let gray_image = image.to_luma8();
+        let mut channel = Array2::zeros((height as usize, width as usize));
+        for (x, y, pixel) in gray_image.enumerate_pixels() {
+            channel[[y as usize, x as usize]] = pixel[0] as f64 / 255.0;
+        }
+        let dim = channel.dim();
+        let (padded, _) = self.window_and_pad(&channel)?;
+        let (h, w) = padded.dim();
+        self.fft.ensure_size(h, w);
+        let spectrum = self.fft.forward(&padded)?;
+        Ok(SpectrumInspection::new(
+            &spectrum,
+            (h, w),
+            dim,
+            self.filter_mask(h, w, dim),
+            self.scramble_weights(h, w, dim),
+        ))
+    }
+
+    /// Combines the amplitude spectrum of `amplitude_image` with the phase spectrum of
+    /// `phase_image` (phase/amplitude swap). The weights in `swap_opts` interpolate from the
+    /// other image's spectrum (0.0) to the swapped one (1.0): amplitudes linearly, phases along
+    /// the shorter arc. Padding, `window`, `grayscale` and the frequency filter follow the scrambler's
+    /// options; scrambling options are ignored. Both images must have the same size.
+    pub fn swap_spectra(
+        &mut self,
+        amplitude_image: &DynamicImage,
+        phase_image: &DynamicImage,
+        swap_opts: &SpectrumSwapOptions,
+    ) -> Result<DynamicImage> {
+        if amplitude_image.dimensions() != phase_image.dimensions() {
+            anyhow::bail!(
+                "Images must have the same size for a spectrum swap, got {:?} and {:?}",
+                amplitude_image.dimensions(),
+                phase_image.dimensions()
+            );
+        }
+        let (width, height) = phase_image.dimensions();
+        self.width = width as usize;
+        self.height = height as usize;
+        let channels_of = |image: &DynamicImage| -> Result<Vec<Array2<f64>>> {
+            if self.options.grayscale {
+                let gray_image = image.to_luma8();
+                let mut channel = Array2::zeros((height as usize, width as usize));
+                for (x, y, pixel) in gray_image.enumerate_pixels() {
+                    channel[[y as usize, x as usize]] = pixel[0] as f64 / 255.0;
+                }
+                Ok(vec![channel])
+            } else {
+                self.split_channels(image)
+            }
+        };
+        let amplitude_channels = channels_of(amplitude_image)?;
+        let phase_channels = channels_of(phase_image)?;
+        let dim = phase_channels[0].dim();
+
+        let (padded, means): (Vec<_>, Vec<_>) = amplitude_channels
+            .iter()
+            .chain(&phase_channels)
+            .map(|channel| self.window_and_pad(channel))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .unzip();
+        let (amplitude_means, phase_means) = means.split_at(amplitude_channels.len());
+        let (h, w) = padded[0].dim();
+        self.fft.ensure_size(h, w);
+        let spectra = padded
+            .par_iter()
+            .map(|channel| self.fft.forward(channel))
+            .collect::<Result<Vec<_>>>()?;
+        let (amplitude_spectra, phase_spectra) = spectra.split_at(amplitude_channels.len());
+
+        let amplitude_weight = swap_opts.amplitude_weight as f64;
+        let phase_weight = swap_opts.phase_weight as f64;
+        let processed = amplitude_spectra
+            .par_iter()
+            .zip(phase_spectra)
+            .zip(amplitude_means.par_iter().zip(phase_means))
+            .map(|((amplitude_source, phase_source), (amplitude_mean, phase_mean))| {
+                let mut combined: Vec<Complex64> = amplitude_source
+                    .iter()
+                    .zip(phase_source)
+                    .map(|(a, p)| {
+                        let magnitude = p.norm() + amplitude_weight * (a.norm() - p.norm());
+                        let phase = a.arg() + phase_weight * angle_difference(p.arg(), a.arg());
+                        Complex64::from_polar(magnitude, phase)
+                    })
+                    .collect();
+                self.apply_frequency_filter(&mut combined, h, w, dim);
+                let mut result = self.remove_padding(&self.fft.inverse(&combined)?, dim)?;
+                // The mean is the DC amplitude, so it follows the amplitude weight
+                if let (Some(a), Some(p)) = (amplitude_mean, phase_mean) {
+                    restore_mean(&mut result, p + amplitude_weight * (a - p));
+                }
+                for val in result.iter_mut() {
+                    *val = val.clamp(0.0, 1.0);
+                }
+                Ok(result)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if self.options.grayscale {
+            let mut output = image::GrayImage::new(width, height);
+            for (x, y, pixel) in output.enumerate_pixels_mut() {
+                *pixel = image::Luma([(processed[0][[y as usize, x as usize]] * 255.0) as u8]);
+            }
+            return Ok(DynamicImage::ImageLuma8(output));
+        }
+        self.combine_channels(processed, None)
     }
 
     fn split_channels(&self, image: &DynamicImage) -> Result<Vec<Array2<f64>>> {
@@ -303,8 +502,30 @@ impl FourierScrambler {
         Ok(channels)
     }
 
-    fn combine_channels(&self, channels: Vec<Array2<f64>>) -> Result<DynamicImage> {
+    /// Returns the alpha channel for the output: scrambled like a color channel
+    /// for `AlphaPolicy::Scramble`, unchanged otherwise.
+    fn finish_alpha(&mut self, alpha: Option<Array2<f64>>, policy: Option<&AlphaPolicy>) -> Result<Option<Array2<f64>>> {
+        match (alpha, policy) {
+            (Some(alpha), Some(AlphaPolicy::Scramble)) => Ok(Some(self.process_channel(alpha)?)),
+            (alpha, _) => Ok(alpha),
+        }
+    }
+
+    fn combine_channels(&self, channels: Vec<Array2<f64>>, alpha: Option<Array2<f64>>) -> Result<DynamicImage> {
         let (width, height) = (self.width as u32, self.height as u32);
+        if let Some(alpha) = alpha {
+            let mut image = ImageBuffer::new(width, height);
+            for y in 0..height {
+                for x in 0..width {
+                    let r = (channels[0][[y as usize, x as usize]] * 255.0) as u8;
+                    let g = (channels[1][[y as usize, x as usize]] * 255.0) as u8;
+                    let b = (channels[2][[y as usize, x as usize]] * 255.0) as u8;
+                    let a = (alpha[[y as usize, x as usize]] * 255.0) as u8;
+                    image.put_pixel(x, y, Rgba([r, g, b, a]));
+                }
+            }
+            return Ok(DynamicImage::ImageRgba8(image));
+        }
         let mut image = ImageBuffer::new(width, height);
         for y in 0..height {
             for x in 0..width {
@@ -317,10 +538,27 @@ impl FourierScrambler {
         Ok(DynamicImage::ImageRgb8(image))
     }
 
+    /// Applies the `window` option and pads the channel. With a window, the channel's mean is
+    /// subtracted before tapering, so the edges fade to the mean luminance rather than to black;
+    /// the mean is returned to be restored after the inverse transform.
+    fn window_and_pad(&self, channel: &Array2<f64>) -> Result<(Array2<f64>, Option<f64>)> {
+        let Some(window) = &self.options.window else {
+            return Ok((self.apply_padding(channel)?, None));
+        };
+        let (height, width) = channel.dim();
+        let mean = channel.mean().unwrap_or(0.0);
+        let window_y = window_1d(window, height);
+        let window_x = window_1d(window, width);
+        let windowed = Array2::from_shape_fn((height, width), |(y, x)| {
+            (channel[[y, x]] - mean) * window_y[y] * window_x[x]
+        });
+        Ok((self.apply_padding(&windowed)?, Some(mean)))
+    }
+
     fn apply_padding(&self, channel: &Array2<f64>) -> Result<Array2<f64>> {
         let (height, width) = channel.dim();
-        let padded_size = get_optimal_fft_size(width.max(height));
-        let mut padded = Array2::zeros((padded_size, padded_size));
+        let (padded_height, padded_width) = padded_dims(height, width);
+        let mut padded = Array2::zeros((padded_height, padded_width));
         match self.options.padding_mode {
             PaddingMode::Zero => {
                 for y in 0..height {
@@ -331,8 +569,8 @@ impl FourierScrambler {
             },
             PaddingMode::Reflect => {
                 // Use reflect_index to fill entire padded image.
-                for y in 0..padded_size {
-                    for x in 0..padded_size {
+                for y in 0..padded_height {
+                    for x in 0..padded_width {
                         let src_y = reflect_index(y, height);
                         let src_x = reflect_index(x, width);
                         padded[[y, x]] = channel[[src_y, src_x]];
@@ -340,8 +578,8 @@ impl FourierScrambler {
                 }
             },
             PaddingMode::Wrap => {
-                for y in 0..padded_size {
-                    for x in 0..padded_size {
+                for y in 0..padded_height {
+                    for x in 0..padded_width {
                         padded[[y, x]] = channel[[y % height, x % width]];
                     }
                 }
@@ -350,35 +588,222 @@ impl FourierScrambler {
         Ok(padded)
     }
 
-    fn remove_padding(&self, complex_data: &[Complex64], original_dim: (usize, usize)) -> Result<Array2<f64>> {
+    fn remove_padding(&self, padded: &Array2<f64>, original_dim: (usize, usize)) -> Result<Array2<f64>> {
         let (height, width) = original_dim;
-        let padded_size = get_optimal_fft_size(width.max(height));
         let mut result = Array2::zeros((height, width));
         for y in 0..height {
             for x in 0..width {
-                let idx = y * padded_size + x;
-                result[[y, x]] = complex_data[idx].re;
+                result[[y, x]] = padded[[y, x]];
             }
         }
         Ok(result)
     }
 }
 
-/// Returns the next power of two greater than or equal to `size`.
+impl Scrambler for FourierScrambler {
+    /// Scrambles a single image.
+    /// If the `grayscale` option is enabled, the image is first converted to grayscale,
+    /// processed as a single luminance channel, and returned as a grayscale image.
+    /// Otherwise, each color channel is processed separately.
+    /// Magnitude and phase scrambling are applied according to `magnitude_scramble`
+    /// and `phase_scramble`; enabling both randomizes the full spectrum.
+    /// With `PhaseMode::Shared` all color channels receive the same random phase field.
+    /// Transparent images keep their alpha channel (`AlphaPolicy::PassThrough`) unless
+    /// `alpha_policy` says otherwise.
+    fn scramble(&mut self, image: &DynamicImage) -> Result<DynamicImage> {
+        let alpha_policy = match self.options.alpha_policy.clone().unwrap_or(AlphaPolicy::PassThrough) {
+            AlphaPolicy::Discard => None,
+            policy => image.color().has_alpha().then_some(policy),
+        };
+        let alpha = alpha_policy.as_ref().map(|_| alpha_channel(image));
+        let filled;
+        let image = if alpha_policy == Some(AlphaPolicy::OpaqueOnly) {
+            filled = fill_transparent_with_mean(image);
+            &filled
+        } else {
+            image
+        };
+
+        if self.options.grayscale {
+            let gray_image = image.to_luma8();
+            let (width, height) = gray_image.dimensions();
+            let mut channel = Array2::zeros((height as usize, width as usize));
+            for y in 0..(height as usize) {
+                for x in 0..(width as usize) {
+                    channel[[y, x]] = gray_image.get_pixel(x as u32, y as u32)[0] as f64 / 255.0;
+                }
+            }
+            let processed_channel = self.process_channel(channel)?;
+            let alpha = self.finish_alpha(alpha, alpha_policy.as_ref())?;
+            if let Some(alpha) = alpha {
+                let mut output = image::GrayAlphaImage::new(width, height);
+                for y in 0..(height as usize) {
+                    for x in 0..(width as usize) {
+                        let val = (processed_channel[[y, x]] * 255.0).clamp(0.0, 255.0) as u8;
+                        let a = (alpha[[y, x]] * 255.0).clamp(0.0, 255.0) as u8;
+                        output.put_pixel(x as u32, y as u32, image::LumaA([val, a]));
+                    }
+                }
+                return Ok(DynamicImage::ImageLumaA8(output));
+            }
+            let mut output = image::GrayImage::new(width, height);
+            for y in 0..(height as usize) {
+                for x in 0..(width as usize) {
+                    let val = (processed_channel[[y, x]] * 255.0).clamp(0.0, 255.0) as u8;
+                    output.put_pixel(x as u32, y as u32, image::Luma([val]));
+                }
+            }
+            return Ok(DynamicImage::ImageLuma8(output));
+        }
+
+        let (width, height) = image.dimensions();
+        self.width = width as usize;
+        self.height = height as usize;
+        let channels = self.split_channels(image)?;
+        let processed_channels = self.process_channels(channels)?;
+        let alpha = self.finish_alpha(alpha, alpha_policy.as_ref())?;
+        self.combine_channels(processed_channels, alpha)
+    }
+}
+
+/// Combines the amplitude spectrum of `amplitude_image` with the phase spectrum of `phase_image`,
+/// see `FourierScrambler::swap_spectra`.
+pub fn swap_spectra(
+    amplitude_image: &DynamicImage,
+    phase_image: &DynamicImage,
+    fourier_opts: &FourierOptions,
+    swap_opts: &SpectrumSwapOptions,
+) -> Result<DynamicImage> {
+    let (width, height) = phase_image.dimensions();
+    let mut scrambler = FourierScrambler::new(width as usize, height as usize, fourier_opts.clone(), Some(0));
+    scrambler.swap_spectra(amplitude_image, phase_image, swap_opts)
+}
+
+/// Extracts the alpha channel normalized to `[0, 1]`.
+fn alpha_channel(image: &DynamicImage) -> Array2<f64> {
+    let rgba = image.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let mut alpha = Array2::zeros((height as usize, width as usize));
+    for (x, y, pixel) in rgba.enumerate_pixels() {
+        alpha[[y as usize, x as usize]] = pixel[3] as f64 / 255.0;
+    }
+    alpha
+}
+
+/// Blends every pixel towards the alpha-weighted mean color by its transparency,
+/// so fully transparent areas become the mean opaque color and do not leak
+/// arbitrary hidden colors into the spectrum.
+fn fill_transparent_with_mean(image: &DynamicImage) -> DynamicImage {
+    let mut rgba = image.to_rgba8();
+    let mut sum = [0.0f64; 3];
+    let mut weight = 0.0f64;
+    for pixel in rgba.pixels() {
+        let a = pixel[3] as f64 / 255.0;
+        for (sum, &value) in sum.iter_mut().zip(&pixel.0) {
+            *sum += value as f64 * a;
+        }
+        weight += a;
+    }
+    if weight <= 0.0 {
+        return DynamicImage::ImageRgba8(rgba);
+    }
+    let mean = sum.map(|v| v / weight);
+    for pixel in rgba.pixels_mut() {
+        let a = pixel[3] as f64 / 255.0;
+        for (value, &mean) in pixel.0.iter_mut().zip(&mean) {
+            *value = (*value as f64 * a + mean * (1.0 - a)).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    DynamicImage::ImageRgba8(rgba)
+}
+
+/// Padded `(height, width)` for an image; each axis is padded on its own.
+fn padded_dims(height: usize, width: usize) -> (usize, usize) {
+    (get_optimal_fft_size(height), get_optimal_fft_size(width))
+}
+
+/// Returns the smallest size greater than or equal to `size` whose prime factors are
+/// all 2, 3, 5 or 7. rustfft handles these mixed-radix lengths about as fast as powers of
+/// two, and they stay much closer to the original size (e.g. 1080 instead of 2048).
 fn get_optimal_fft_size(size: usize) -> usize {
-    let mut optimal_size = size;
-    while !is_power_of_two(optimal_size) {
+    let mut optimal_size = size.max(1);
+    while !is_smooth(optimal_size) {
         optimal_size += 1;
     }
     optimal_size
 }
 
-fn is_power_of_two(n: usize) -> bool {
-    n != 0 && (n & (n - 1)) == 0
+fn is_smooth(mut n: usize) -> bool {
+    for factor in [2, 3, 5, 7] {
+        while n.is_multiple_of(factor) {
+            n /= factor;
+        }
+    }
+    n == 1
+}
+
+/// Random phase offsets in `[-pi, pi)` for one half of an `h x w` spectrum. Self-conjugate
+/// coefficients (DC and Nyquist) get no offset, so they stay real and the mean is kept.
+pub(crate) fn random_phase_field(rng: &mut impl Rng, h: usize, w: usize) -> Vec<f64> {
+    let mut offsets = vec![0.0; h * w];
+    for y in 0..h {
+        for x in 0..w {
+            let (sym_y, sym_x) = conjugate_index(y, x, h, w);
+            if y > sym_y || (y == sym_y && x >= sym_x) {
+                continue;
+            }
+            offsets[y * w + x] = rng.random_range(-std::f64::consts::PI..std::f64::consts::PI);
+        }
+    }
+    offsets
+}
+
+/// Index of the coefficient holding the complex conjugate of `(y, x)` in the spectrum of a real signal.
+pub(crate) fn conjugate_index(y: usize, x: usize, h: usize, w: usize) -> (usize, usize) {
+    ((h - y) % h, (w - x) % w)
+}
+
+/// Symmetric window of `len` samples, 1.0 in the center.
+fn window_1d(window: &WindowFunction, len: usize) -> Vec<f64> {
+    if len < 2 {
+        return vec![1.0; len];
+    }
+    let last = (len - 1) as f64;
+    let hann = |t: f64| 0.5 * (1.0 - (2.0 * std::f64::consts::PI * t).cos());
+    (0..len)
+        .map(|n| {
+            // Position in [0, 1] along the axis
+            let t = n as f64 / last;
+            match window {
+                WindowFunction::Hann => hann(t),
+                WindowFunction::Tukey(taper) => {
+                    let taper = (*taper as f64).clamp(0.0, 1.0);
+                    let edge = t.min(1.0 - t);
+                    if edge >= taper / 2.0 {
+                        1.0
+                    } else {
+                        // Rising half of a Hann window of length `taper`
+                        hann(edge / taper)
+                    }
+                }
+                WindowFunction::Gaussian(sigma) => {
+                    let sigma = (*sigma as f64).max(f64::EPSILON);
+                    let offset = (2.0 * t - 1.0) / sigma;
+                    (-0.5 * offset * offset).exp()
+                }
+            }
+        })
+        .collect()
+}
+
+/// Shifts `channel` so that its mean becomes `mean`.
+fn restore_mean(channel: &mut Array2<f64>, mean: f64) {
+    let shift = mean - channel.mean().unwrap_or(mean);
+    channel.mapv_inplace(|value| value + shift);
 }
 
 /// Reflects index `x` for an original size `size` using symmetric reflection.
-fn reflect_index(x: usize, size: usize) -> usize {
+pub(crate) fn reflect_index(x: usize, size: usize) -> usize {
     if size == 0 {
         return 0;
     }
@@ -406,3 +831,200 @@ fn angle_difference(a: f64, b: f64) -> f64 {
     }
     diff
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    fn random_spectrum(h: usize, w: usize) -> Vec<Complex64> {
+        let mut rng = StdRng::seed_from_u64(11);
+        let input = Array2::from_shape_fn((h, w), |_| rng.random::<f64>());
+        Fft2d::new(h, w).forward(&input).unwrap()
+    }
+
+    fn assert_conjugate_symmetric(data: &[Complex64], h: usize, w: usize) {
+        for y in 0..h {
+            for x in 0..w {
+                let (sym_y, sym_x) = conjugate_index(y, x, h, w);
+                assert!((data[y * w + x] - data[sym_y * w + sym_x].conj()).norm() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn magnitude_scramble_keeps_phase_and_shuffles_amplitude() {
+        let (h, w) = (12, 15);
+        let original = random_spectrum(h, w);
+        let mut data = original.clone();
+        let options = FourierOptions { magnitude_scramble: true, phase_scramble: false, ..FourierOptions::default() };
+        FourierScrambler::new(w, h, options, Some(5)).magnitude_scramble(&mut data, h, w, None);
+
+        assert_eq!(data[0], original[0]);
+        assert_conjugate_symmetric(&data, h, w);
+        for (new, old) in data.iter().zip(&original) {
+            if new.norm() > 1e-9 && old.norm() > 1e-9 {
+                assert!(angle_difference(new.arg(), old.arg()).abs() < 1e-9);
+            }
+        }
+        // Magnitudes only move within their band, so the set of magnitudes is unchanged
+        let sorted = |data: &[Complex64]| {
+            let mut magnitudes: Vec<f64> = data.iter().map(|c| c.norm()).collect();
+            magnitudes.sort_by(f64::total_cmp);
+            magnitudes
+        };
+        for (a, b) in sorted(&data).iter().zip(sorted(&original)) {
+            assert!((a - b).abs() < 1e-9);
+        }
+        assert!(data.iter().zip(&original).any(|(new, old)| (new.norm() - old.norm()).abs() > 1e-6));
+    }
+
+    /// Left half fully transparent, right half partly transparent, with a gradient in color.
+    fn cut_out_image() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(16, 12, |x, y| {
+            Rgba([(x * 15) as u8, (y * 20) as u8, 90, if x < 8 { 0 } else { 200 }])
+        }))
+    }
+
+    fn scramble(image: &DynamicImage, options: FourierOptions) -> DynamicImage {
+        let mut scrambler = FourierScrambler::new(image.width() as usize, image.height() as usize, options, Some(3));
+        scrambler.scramble(image).unwrap()
+    }
+
+    /// Phase-scrambles `spectrum` like `process_channels` does, with seed 5.
+    fn scramble_spectrum_with(options: FourierOptions, spectrum: &[Complex64], h: usize, w: usize) -> Vec<Complex64> {
+        let mut scrambler = FourierScrambler::new(w, h, options, Some(5));
+        let weights = scrambler.scramble_weights(h, w, (h, w));
+        let mut data = spectrum.to_vec();
+        scrambler.scramble_spectrum(&mut data, (h, w), (h, w), None, weights.as_deref());
+        data
+    }
+
+    #[test]
+    fn scramble_band_leaves_other_frequencies_intact() {
+        let (h, w) = (16, 16);
+        let original = random_spectrum(h, w);
+        let banded = FourierOptions { scramble_band: Some(FrequencyRange::HighPass(0.5)), ..FourierOptions::default() };
+        let data = scramble_spectrum_with(banded, &original, h, w);
+        let full = scramble_spectrum_with(FourierOptions::default(), &original, h, w);
+
+        for y in 0..h {
+            for x in 0..w {
+                let idx = y * w + x;
+                // Nothing is filtered away, and amplitudes are kept everywhere
+                assert!((data[idx].norm() - original[idx].norm()).abs() < 1e-9);
+                if normalized_radius(y, x, h, w) < 0.5 {
+                    assert!((data[idx] - original[idx]).norm() < 1e-9);
+                } else {
+                    // The random draws do not depend on the band
+                    assert!((data[idx] - full[idx]).norm() < 1e-9);
+                }
+            }
+        }
+        assert!(data.iter().zip(&original).any(|(new, old)| (new - old).norm() > 1e-6));
+    }
+
+    #[test]
+    fn shared_phase_preserves_gray() {
+        let image = DynamicImage::ImageRgb8(image::RgbImage::from_fn(16, 12, |x, y| {
+            let value = ((x * 7 + y * 13) % 29 * 8) as u8;
+            Rgb([value, value, value])
+        }));
+        let is_gray = |phase_mode: PhaseMode| {
+            let options = FourierOptions { phase_mode: Some(phase_mode), ..FourierOptions::default() };
+            scramble(&image, options).to_rgb8().pixels().all(|p| p[0] == p[1] && p[1] == p[2])
+        };
+        assert!(is_gray(PhaseMode::Shared));
+        assert!(!is_gray(PhaseMode::Independent));
+    }
+
+    #[test]
+    fn alpha_is_kept_by_default() {
+        let image = cut_out_image();
+        for grayscale in [false, true] {
+            let output = scramble(&image, FourierOptions { grayscale, ..FourierOptions::default() });
+            assert!(output.color().has_alpha());
+            for (input, output) in image.to_rgba8().pixels().zip(output.to_rgba8().pixels()) {
+                assert_eq!(input[3], output[3]);
+            }
+        }
+    }
+
+    #[test]
+    fn alpha_is_dropped_on_request() {
+        let options = FourierOptions { alpha_policy: Some(AlphaPolicy::Discard), ..FourierOptions::default() };
+        assert!(!scramble(&cut_out_image(), options).color().has_alpha());
+    }
+
+    fn textured_image(offset: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(image::RgbImage::from_fn(16, 12, |x, y| {
+            Rgb([(x * 13 + offset) as u8, ((x + y * offset) % 7 * 30) as u8, (y * 17) as u8])
+        }))
+    }
+
+    fn assert_close(a: &DynamicImage, b: &DynamicImage) {
+        for (a, b) in a.to_rgb8().pixels().zip(b.to_rgb8().pixels()) {
+            for (a, b) in a.0.iter().zip(b.0) {
+                assert!(a.abs_diff(b) <= 1, "{a} != {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn swap_weights_pick_the_source_spectra() {
+        let (amplitude_image, phase_image) = (textured_image(3), textured_image(40));
+        let swap = |amplitude_weight, phase_weight| {
+            let swap_opts = SpectrumSwapOptions { amplitude_weight, phase_weight };
+            swap_spectra(&amplitude_image, &phase_image, &FourierOptions::default(), &swap_opts).unwrap()
+        };
+        // Taking both spectra from the same image gives that image back
+        assert_close(&swap(0.0, 1.0), &phase_image);
+        assert_close(&swap(1.0, 0.0), &amplitude_image);
+        let same = swap_spectra(&phase_image, &phase_image, &FourierOptions::default(), &SpectrumSwapOptions::default());
+        assert_close(&same.unwrap(), &phase_image);
+        assert!(swap(1.0, 1.0).to_rgb8().pixels().zip(phase_image.to_rgb8().pixels()).any(|(a, b)| a != b));
+    }
+
+    #[test]
+    fn swap_rejects_mismatched_sizes() {
+        let small = DynamicImage::ImageRgb8(image::RgbImage::new(8, 8));
+        let options = FourierOptions::default();
+        assert!(swap_spectra(&textured_image(3), &small, &options, &SpectrumSwapOptions::default()).is_err());
+    }
+
+    #[test]
+    fn windows_are_symmetric_and_peak_in_the_center() {
+        for window in [WindowFunction::Hann, WindowFunction::Tukey(0.5), WindowFunction::Gaussian(0.4)] {
+            let samples = window_1d(&window, 17);
+            assert!((samples[8] - 1.0).abs() < 1e-12);
+            for (a, b) in samples.iter().zip(samples.iter().rev()) {
+                assert!((a - b).abs() < 1e-12);
+            }
+            assert!(samples.iter().all(|&v| (0.0..=1.0).contains(&v)));
+        }
+        assert!(window_1d(&WindowFunction::Tukey(0.0), 9).iter().all(|&v| v == 1.0));
+        let tukey = window_1d(&WindowFunction::Tukey(1.0), 9);
+        for (a, b) in tukey.iter().zip(window_1d(&WindowFunction::Hann, 9)) {
+            assert!((a - b).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn windowed_scramble_keeps_the_mean() {
+        let image = DynamicImage::ImageLuma8(image::GrayImage::from_fn(24, 16, |x, y| {
+            image::Luma([100 + ((x * 7 + y * 5) % 11 * 4) as u8])
+        }));
+        let mean = |image: &DynamicImage| {
+            let gray = image.to_luma8();
+            gray.pixels().map(|p| p[0] as f64).sum::<f64>() / gray.len() as f64
+        };
+        let options = FourierOptions { window: Some(WindowFunction::Hann), grayscale: true, ..FourierOptions::default() };
+        let output = scramble(&image, options);
+        // Up to the truncation to 8 bits
+        assert!((mean(&output) - mean(&image)).abs() < 1.0);
+
+        let mut channel = Array2::from_elem((4, 4), 0.2);
+        restore_mean(&mut channel, 0.6);
+        assert!(channel.iter().all(|&v| (v - 0.6).abs() < 1e-12));
+    }
+}
@@ -0,0 +1,170 @@
+use std::path::Path;
+use anyhow::Context;
+use image::{GrayImage, Luma};
+use num_complex::Complex64;
+use serde::{Serialize, Deserialize};
+use super::filter::{ring_indices, rotational_average, FrequencyGrid};
+use super::types::FrequencyUnits;
+use crate::Result;
+
+/// Spectrum of one image as seen by a `FourierScrambler`, see `FourierScrambler::inspect`.
+/// Per-coefficient values are row-major `height x width` in FFT order (DC at index 0).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SpectrumInspection {
+    /// Size of the padded spectrum
+    pub width: usize,
+    pub height: usize,
+    pub amplitude: Vec<f64>,
+    /// Phase in radians, in `[-pi, pi]`
+    pub phase: Vec<f64>,
+    /// Gain of the frequency filter, `None` when nothing is filtered
+    pub filter_mask: Option<Vec<f64>>,
+    /// How far each coefficient is scrambled, `None` when every coefficient is scrambled fully
+    pub scramble_mask: Option<Vec<f64>>,
+    /// Mean amplitude per ring of radial frequency in cycles per image (index 0 is DC)
+    pub radial_average: Vec<f64>,
+    /// Slope of log amplitude over log frequency, fitted to `radial_average` from 1 cycle per
+    /// image up to Nyquist; natural images are close to -1. `None` for images too small to fit.
+    pub slope: Option<f64>,
+}
+
+impl SpectrumInspection {
+    /// `image_dim` is the `(height, width)` of the image before padding.
+    pub(crate) fn new(
+        spectrum: &[Complex64],
+        (height, width): (usize, usize),
+        image_dim: (usize, usize),
+        filter_mask: Option<Vec<f64>>,
+        scramble_mask: Option<Vec<f64>>,
+    ) -> Self {
+        let amplitude: Vec<f64> = spectrum.iter().map(|c| c.norm()).collect();
+        let phase = spectrum.iter().map(|c| c.arg()).collect();
+        let grid = FrequencyGrid::new(height, width, image_dim, &FrequencyUnits::CyclesPerImage);
+        let rings = ring_indices(&grid, height, width);
+        let ring_count = rings.iter().max().map_or(0, |max| max + 1);
+        let radial_average = rotational_average(&amplitude, &rings, ring_count);
+        let nyquist = image_dim.0.max(image_dim.1) / 2;
+        let slope = fit_slope(&radial_average, nyquist);
+        Self { width, height, amplitude, phase, filter_mask, scramble_mask, radial_average, slope }
+    }
+
+    /// Log-scaled amplitude, shifted so that DC is in the center.
+    pub fn amplitude_image(&self) -> GrayImage {
+        let log_amplitude: Vec<f64> = self.amplitude.iter().map(|a| (1.0 + a).ln()).collect();
+        let max = log_amplitude.iter().cloned().fold(0.0, f64::max);
+        let scale = if max > 0.0 { 1.0 / max } else { 0.0 };
+        let normalized: Vec<f64> = log_amplitude.iter().map(|value| value * scale).collect();
+        centered_image(&normalized, self.width, self.height)
+    }
+
+    /// Phase from black (-pi) to white (pi), shifted so that DC is in the center.
+    pub fn phase_image(&self) -> GrayImage {
+        let normalized: Vec<f64> = self
+            .phase
+            .iter()
+            .map(|phase| (phase + std::f64::consts::PI) / (2.0 * std::f64::consts::PI))
+            .collect();
+        centered_image(&normalized, self.width, self.height)
+    }
+
+    pub fn filter_image(&self) -> Option<GrayImage> {
+        self.filter_mask.as_ref().map(|mask| centered_image(mask, self.width, self.height))
+    }
+
+    pub fn scramble_image(&self) -> Option<GrayImage> {
+        self.scramble_mask.as_ref().map(|mask| centered_image(mask, self.width, self.height))
+    }
+
+    /// Writes `<stem>_amplitude.png`, `<stem>_phase.png`, `<stem>_filter.png` and
+    /// `<stem>_scramble.png` (for masks that exist) to `dir`, and every value as `<stem>_spectrum.json`.
+    pub fn save(&self, dir: &Path, stem: &str) -> Result<()> {
+        let save_image = |image: GrayImage, name: &str| {
+            let path = dir.join(format!("{}_{}.png", stem, name));
+            image.save(&path)
+                .with_context(|| format!("Failed to save spectrum: {}", path.display()))
+        };
+        save_image(self.amplitude_image(), "amplitude")?;
+        save_image(self.phase_image(), "phase")?;
+        if let Some(image) = self.filter_image() {
+            save_image(image, "filter")?;
+        }
+        if let Some(image) = self.scramble_image() {
+            save_image(image, "scramble")?;
+        }
+        let path = dir.join(format!("{}_spectrum.json", stem));
+        std::fs::write(&path, serde_json::to_string(self)?)
+            .with_context(|| format!("Failed to write spectrum: {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// Image of `values` in `[0, 1]`, given in FFT order, shifted so that DC is in the center.
+pub(crate) fn centered_image(values: &[f64], width: usize, height: usize) -> GrayImage {
+    GrayImage::from_fn(width as u32, height as u32, |x, y| {
+        let src_y = (y as usize + height - height / 2) % height;
+        let src_x = (x as usize + width - width / 2) % width;
+        Luma([(values[src_y * width + src_x].clamp(0.0, 1.0) * 255.0).round() as u8])
+    })
+}
+
+/// Least-squares slope of `ln(amplitude)` over `ln(f)` for the rings `1..=max_ring`.
+fn fit_slope(radial_average: &[f64], max_ring: usize) -> Option<f64> {
+    let points: Vec<(f64, f64)> = radial_average
+        .iter()
+        .enumerate()
+        .take(max_ring + 1)
+        .skip(1)
+        .filter(|&(_, &amplitude)| amplitude > 0.0)
+        .map(|(ring, &amplitude)| ((ring as f64).ln(), amplitude.ln()))
+        .collect();
+    if points.len() < 2 {
+        return None;
+    }
+    let count = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / count;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / count;
+    let covariance: f64 = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    let variance: f64 = points.iter().map(|(x, _)| (x - mean_x) * (x - mean_x)).sum();
+    Some(covariance / variance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slope_of_a_power_law() {
+        let mut radial_average: Vec<f64> = (0..20).map(|ring| 3.0 * (ring as f64).powf(-1.2)).collect();
+        // Neither DC nor rings beyond `max_ring` enter the fit
+        radial_average[0] = 1e6;
+        radial_average[16..].fill(1.0);
+        assert!((fit_slope(&radial_average, 15).unwrap() + 1.2).abs() < 1e-9);
+        assert_eq!(fit_slope(&radial_average, 1), None);
+    }
+
+    #[test]
+    fn inspection_of_a_power_law_spectrum() {
+        let (height, width) = (32, 32);
+        let grid = FrequencyGrid::new(height, width, (height, width), &FrequencyUnits::CyclesPerImage);
+        let spectrum: Vec<Complex64> = (0..height * width)
+            .map(|idx| {
+                let f = grid.radius(idx / width, idx % width);
+                Complex64::new(if f > 0.0 { f.powi(-2) } else { 10.0 }, 0.0)
+            })
+            .collect();
+        let inspection = SpectrumInspection::new(&spectrum, (height, width), (height, width), None, None);
+        // Rounding frequencies to rings blurs the power law a little
+        assert!((inspection.slope.unwrap() + 2.0).abs() < 0.1);
+        assert_eq!(inspection.radial_average[0], 10.0);
+    }
+
+    #[test]
+    fn centered_image_puts_dc_in_the_middle() {
+        let (width, height) = (6, 5);
+        let mut values = vec![0.0; width * height];
+        values[0] = 1.0;
+        let image = centered_image(&values, width, height);
+        assert_eq!(image.get_pixel(3, 2)[0], 255);
+        assert_eq!(image.pixels().filter(|p| p[0] > 0).count(), 1);
+    }
+}
@@ -0,0 +1,369 @@
+use std::path::{Path, PathBuf};
+use anyhow::Context;
+use image::{imageops, RgbaImage};
+use face_detection::FaceRegion;
+use super::types::{
+    BackgroundMode, FaceDetectionOptions, MaskFalloff, RegionMaskOptions, RegionShape, ScrambleOptions, SoftMaskOptions,
+};
+use crate::Result;
+
+/// Per-pixel scramble weights in `[0, 1]`, stored row by row.
+/// 1.0 means fully scrambled, 0.0 means untouched.
+#[derive(Debug, Clone)]
+pub struct RegionMask {
+    width: u32,
+    height: u32,
+    weights: Vec<f32>,
+    /// Bounding box of every shape added to the mask, grown to cover its softened edge.
+    boxes: Vec<FaceRegion>,
+}
+
+impl RegionMask {
+    /// Creates an empty mask (nothing is scrambled).
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            weights: vec![0.0; (width * height) as usize],
+            boxes: Vec::new(),
+        }
+    }
+
+    /// Rasterizes the shapes of `options` and applies its soft edge, if any.
+    pub fn from_options(options: &RegionMaskOptions, width: u32, height: u32, frame_index: usize) -> Result<Self> {
+        let mut mask = Self::from_shapes(&options.regions, width, height, frame_index)?;
+        if let Some(soft_mask) = &options.soft_mask {
+            mask.soften(soft_mask);
+        }
+        Ok(mask)
+    }
+
+    /// Rasterizes user-defined shapes. `frame_index` selects the mask file
+    /// of a `RegionShape::MaskSequence`; pass 0 for still images.
+    pub fn from_shapes(shapes: &[RegionShape], width: u32, height: u32, frame_index: usize) -> Result<Self> {
+        let mut mask = Self::new(width, height);
+        for shape in shapes {
+            match shape {
+                RegionShape::Rectangle { x, y, width: rect_w, height: rect_h } => {
+                    mask.add_rectangle(*x, *y, x.saturating_add(*rect_w), y.saturating_add(*rect_h));
+                }
+                RegionShape::Ellipse { center_x, center_y, radius_x, radius_y } => {
+                    mask.add_ellipse(*center_x, *center_y, *radius_x, *radius_y);
+                }
+                RegionShape::Polygon(vertices) => mask.add_polygon(vertices),
+                RegionShape::MaskImage(path) => mask.add_mask_image(path)?,
+                RegionShape::MaskSequence(dir) => {
+                    let path = mask_sequence_frame(dir, frame_index)?;
+                    mask.add_mask_image(&path)?;
+                }
+            }
+        }
+        Ok(mask)
+    }
+
+    /// Builds a mask covering the given face bounding boxes.
+    pub fn from_face_regions(regions: &[FaceRegion], width: u32, height: u32) -> Self {
+        let mut mask = Self::new(width, height);
+        for region in regions {
+            mask.add_rectangle(region.x1, region.y1, region.x2, region.y2);
+        }
+        mask
+    }
+
+    /// Builds the mask for detected faces: their bounding boxes, or ellipses when
+    /// `face_ellipse` is set, softened according to `soft_mask`.
+    pub fn from_face_detections(
+        regions: &[FaceRegion],
+        width: u32,
+        height: u32,
+        face_opts: &FaceDetectionOptions,
+    ) -> Self {
+        let mut mask = match &face_opts.face_ellipse {
+            Some(ellipse) => {
+                let mut mask = Self::new(width, height);
+                for region in regions {
+                    let (region_width, region_height) = region.dimensions();
+                    let radius_x = region_width as f32 / 2.0 * ellipse.scale;
+                    let radius_y = radius_x * ellipse.aspect_ratio;
+                    mask.add_ellipse(
+                        region.x1 as f32 + region_width as f32 / 2.0,
+                        region.y1 as f32 + region_height as f32 / 2.0,
+                        radius_x,
+                        radius_y,
+                    );
+                }
+                mask
+            }
+            None => Self::from_face_regions(regions, width, height),
+        };
+        if let Some(soft_mask) = &face_opts.soft_mask {
+            mask.soften(soft_mask);
+        }
+        mask
+    }
+
+    /// The mask that `options` scrambles within: the detected faces (`face_regions`) with face
+    /// detection, the region mask otherwise, or `None` when the whole image is scrambled.
+    pub fn for_options(
+        options: &ScrambleOptions,
+        face_regions: &[FaceRegion],
+        width: u32,
+        height: u32,
+        frame_index: usize,
+    ) -> Result<Option<Self>> {
+        if let Some(face_opts) = &options.face_detection {
+            return Ok(Some(Self::from_face_detections(face_regions, width, height, face_opts)));
+        }
+        match &options.region_mask {
+            Some(mask_opts) => Ok(Some(Self::from_options(mask_opts, width, height, frame_index)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Bounding boxes of the shapes that make up the mask. Together they cover every pixel
+    /// with a non-zero weight, including the feathered edge of a softened mask.
+    pub fn regions(&self) -> &[FaceRegion] {
+        &self.boxes
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Returns the weight at `(x, y)`.
+    pub fn weight(&self, x: u32, y: u32) -> f32 {
+        self.weights[(y * self.width + x) as usize]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.weights.iter().all(|&w| w <= 0.0)
+    }
+
+    /// Replaces the hard mask edge with a smooth transition of `feather_width` pixels.
+    pub fn soften(&mut self, options: &SoftMaskOptions) {
+        let feather_width = options.feather_width.max(0.0);
+        match options.falloff {
+            MaskFalloff::Gaussian => {
+                if feather_width > 0.0 {
+                    // Three sigmas on each side of the edge cover the requested width
+                    let sigma = feather_width / 3.0;
+                    self.weights = gaussian_blur(&self.weights, self.width, self.height, sigma);
+                    // The blur spreads weight up to its kernel radius outside each shape
+                    let radius = (sigma * 3.0).ceil() as u32;
+                    for bbox in self.boxes.iter_mut() {
+                        bbox.x1 = bbox.x1.saturating_sub(radius);
+                        bbox.y1 = bbox.y1.saturating_sub(radius);
+                        bbox.x2 = bbox.x2.saturating_add(radius).min(self.width);
+                        bbox.y2 = bbox.y2.saturating_add(radius).min(self.height);
+                    }
+                }
+            }
+            MaskFalloff::Elliptical => {
+                let mut weights = vec![0.0f32; self.weights.len()];
+                for bbox in &self.boxes {
+                    let radius_x = (bbox.x2 - bbox.x1) as f32 / 2.0;
+                    let radius_y = (bbox.y2 - bbox.y1) as f32 / 2.0;
+                    let center_x = bbox.x1 as f32 + radius_x;
+                    let center_y = bbox.y1 as f32 + radius_y;
+                    let inner = (1.0 - feather_width / radius_x.min(radius_y)).clamp(0.0, 1.0);
+                    for y in bbox.y1..bbox.y2.min(self.height) {
+                        for x in bbox.x1..bbox.x2.min(self.width) {
+                            let dx = (x as f32 + 0.5 - center_x) / radius_x;
+                            let dy = (y as f32 + 0.5 - center_y) / radius_y;
+                            let r = (dx * dx + dy * dy).sqrt();
+                            let falloff = if r >= 1.0 {
+                                0.0
+                            } else if r <= inner {
+                                1.0
+                            } else {
+                                smoothstep((1.0 - r) / (1.0 - inner))
+                            };
+                            let idx = (y * self.width + x) as usize;
+                            weights[idx] = weights[idx].max(self.weights[idx] * falloff);
+                        }
+                    }
+                }
+                self.weights = weights;
+            }
+        }
+    }
+
+    /// Records the bounding box of a newly added shape, clipped to the mask.
+    fn add_box(&mut self, x1: u32, y1: u32, x2: u32, y2: u32) {
+        let (x2, y2) = (x2.min(self.width), y2.min(self.height));
+        if x2 > x1 && y2 > y1 {
+            self.boxes.push(FaceRegion { x1, y1, x2, y2, confidence: 1.0 });
+        }
+    }
+
+    /// Sets the weight at `(x, y)` to `weight` if it is larger than the current one.
+    fn include(&mut self, x: u32, y: u32, weight: f32) {
+        let idx = (y * self.width + x) as usize;
+        self.weights[idx] = self.weights[idx].max(weight.clamp(0.0, 1.0));
+    }
+
+    fn add_rectangle(&mut self, x1: u32, y1: u32, x2: u32, y2: u32) {
+        self.add_box(x1, y1, x2, y2);
+        for y in y1..y2.min(self.height) {
+            for x in x1..x2.min(self.width) {
+                self.include(x, y, 1.0);
+            }
+        }
+    }
+
+    fn add_ellipse(&mut self, center_x: f32, center_y: f32, radius_x: f32, radius_y: f32) {
+        if radius_x <= 0.0 || radius_y <= 0.0 {
+            return;
+        }
+        self.add_box(
+            (center_x - radius_x).max(0.0) as u32,
+            (center_y - radius_y).max(0.0) as u32,
+            (center_x + radius_x).ceil().max(0.0) as u32,
+            (center_y + radius_y).ceil().max(0.0) as u32,
+        );
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let dx = (x as f32 + 0.5 - center_x) / radius_x;
+                let dy = (y as f32 + 0.5 - center_y) / radius_y;
+                if dx * dx + dy * dy <= 1.0 {
+                    self.include(x, y, 1.0);
+                }
+            }
+        }
+    }
+
+    /// Fills a polygon using the even-odd rule, sampled at pixel centers.
+    fn add_polygon(&mut self, vertices: &[(f32, f32)]) {
+        if vertices.len() < 3 {
+            return;
+        }
+        let (min_x, max_x) = vertices.iter().fold((f32::MAX, f32::MIN), |(lo, hi), &(x, _)| (lo.min(x), hi.max(x)));
+        let (min_y, max_y) = vertices.iter().fold((f32::MAX, f32::MIN), |(lo, hi), &(_, y)| (lo.min(y), hi.max(y)));
+        self.add_box(
+            min_x.max(0.0) as u32,
+            min_y.max(0.0) as u32,
+            max_x.ceil().max(0.0) as u32,
+            max_y.ceil().max(0.0) as u32,
+        );
+        for y in 0..self.height {
+            let py = y as f32 + 0.5;
+            for x in 0..self.width {
+                let px = x as f32 + 0.5;
+                let mut inside = false;
+                let mut j = vertices.len() - 1;
+                for i in 0..vertices.len() {
+                    let (xi, yi) = vertices[i];
+                    let (xj, yj) = vertices[j];
+                    if (yi > py) != (yj > py) && px < (xj - xi) * (py - yi) / (yj - yi) + xi {
+                        inside = !inside;
+                    }
+                    j = i;
+                }
+                if inside {
+                    self.include(x, y, 1.0);
+                }
+            }
+        }
+    }
+
+    fn add_mask_image(&mut self, path: &Path) -> Result<()> {
+        let mut gray = image::open(path)
+            .with_context(|| format!("Failed to open mask image: {}", path.display()))?
+            .to_luma8();
+        if gray.dimensions() != (self.width, self.height) {
+            gray = imageops::resize(&gray, self.width, self.height, imageops::FilterType::Triangle);
+        }
+        let (mut x1, mut y1, mut x2, mut y2) = (self.width, self.height, 0, 0);
+        for (x, y, pixel) in gray.enumerate_pixels() {
+            if pixel[0] > 0 {
+                self.include(x, y, pixel[0] as f32 / 255.0);
+                x1 = x1.min(x);
+                y1 = y1.min(y);
+                x2 = x2.max(x + 1);
+                y2 = y2.max(y + 1);
+            }
+        }
+        self.add_box(x1, y1, x2, y2);
+        Ok(())
+    }
+}
+
+fn smoothstep(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Separable Gaussian blur of a single-channel weight map, clamping at the borders.
+fn gaussian_blur(weights: &[f32], width: u32, height: u32, sigma: f32) -> Vec<f32> {
+    let (w, h) = (width as usize, height as usize);
+    let radius = (sigma * 3.0).ceil() as isize;
+    let kernel: Vec<f32> = (-radius..=radius)
+        .map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let norm: f32 = kernel.iter().sum();
+
+    let mut horizontal = vec![0.0f32; weights.len()];
+    for y in 0..h {
+        for x in 0..w {
+            let mut acc = 0.0;
+            for (k, weight) in kernel.iter().enumerate() {
+                let sx = (x as isize + k as isize - radius).clamp(0, w as isize - 1) as usize;
+                acc += weights[y * w + sx] * weight;
+            }
+            horizontal[y * w + x] = acc / norm;
+        }
+    }
+    let mut output = vec![0.0f32; weights.len()];
+    for y in 0..h {
+        for x in 0..w {
+            let mut acc = 0.0;
+            for (k, weight) in kernel.iter().enumerate() {
+                let sy = (y as isize + k as isize - radius).clamp(0, h as isize - 1) as usize;
+                acc += horizontal[sy * w + x] * weight;
+            }
+            output[y * w + x] = acc / norm;
+        }
+    }
+    output
+}
+
+/// Returns the mask file for `frame_index` from a directory of masks sorted by file name.
+/// Frames past the end of the sequence reuse the last mask.
+fn mask_sequence_frame(dir: &Path, frame_index: usize) -> Result<PathBuf> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read mask sequence: {}", dir.display()))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect();
+    files.sort();
+    let last = files.len().checked_sub(1)
+        .ok_or_else(|| anyhow::anyhow!("Mask sequence is empty: {}", dir.display()))?;
+    Ok(files.swap_remove(frame_index.min(last)))
+}
+
+/// Blends `foreground` into `background` by mask weight: `background * (1 - w) + foreground * w`.
+/// With `BackgroundMode::Exclude` the background is replaced by transparent black,
+/// so only the masked foreground remains.
+pub fn composite_with_mask(
+    background: &RgbaImage,
+    foreground: &RgbaImage,
+    mask: &RegionMask,
+    background_mode: &BackgroundMode,
+) -> RgbaImage {
+    let (width, height) = background.dimensions();
+    let mut output = match background_mode {
+        BackgroundMode::Include => background.clone(),
+        BackgroundMode::Exclude => RgbaImage::new(width, height),
+    };
+    for (x, y, pixel) in output.enumerate_pixels_mut() {
+        let w = mask.weight(x, y);
+        if w <= 0.0 {
+            continue;
+        }
+        for (bg, &fg) in pixel.0.iter_mut().zip(&foreground.get_pixel(x, y).0) {
+            *bg = (*bg as f32 * (1.0 - w) + fg as f32 * w).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    output
+}
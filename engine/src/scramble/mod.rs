@@ -1,13 +1,34 @@
 mod pixel;
 mod types;
 mod fourier;
+pub(crate) mod fft;
+pub(crate) mod filter;
 mod block;
 mod blur;
 mod diffeomorphic;
+mod wavelet;
+mod scrambler;
+mod pipeline;
+mod mask;
+mod color;
+mod normalize;
+mod noise;
+pub(crate) mod inspect;
 
 pub use pixel::*;
 pub use types::*;
-pub use fourier::FourierScrambler;
+pub use fourier::{FourierScrambler, swap_spectra};
 pub use block::BlockScrambler;
 pub use blur::BlurScrambler;
-pub use diffeomorphic::DiffeomorphicScrambler;
\ No newline at end of file
+pub use diffeomorphic::DiffeomorphicScrambler;
+pub use wavelet::WaveletScrambler;
+pub use scrambler::{
+    Scrambler, ScrambleOutput, create_scrambler, apply_scrambler, apply_scrambler_with_regions,
+    scramble_image, scramble_image_with_regions, detect_faces,
+};
+pub use pipeline::{PipelineScrambler, derive_seed, derive_step_seeds, resolve_step_seeds};
+pub use mask::{RegionMask, composite_with_mask};
+pub use color::{ColorSpaceScrambler, to_color_space, from_color_space};
+pub use normalize::{LuminanceStats, luminance_stats, average_stats, normalize_luminance, normalize_output};
+pub use noise::generate_noise;
+pub use inspect::SpectrumInspection;
@@ -0,0 +1,185 @@
+use anyhow::Context;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, GrayImage, Luma, Rgb, RgbImage};
+use ndarray::Array2;
+use num_complex::Complex64;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rayon::prelude::*;
+use super::fft::Fft2d;
+use super::filter::FrequencyGrid;
+use super::fourier::{conjugate_index, random_phase_field};
+use super::types::{FrequencyUnits, NoiseOptions, NoiseSpectrum, PhaseMode};
+use crate::Result;
+
+/// Generates a noise image with random phase and the amplitude spectrum described by
+/// `options.spectrum`.
+///
+/// Power-law noise is scaled to mean 0.5 and RMS contrast `options.contrast`, each channel on
+/// its own. Noise matched to an image keeps that image's DC term, so its mean luminance and
+/// contrast carry over. Values outside the displayable range are clipped. With
+/// `PhaseMode::Shared`, every channel gets the same phase, which gives gray power-law noise and
+/// keeps the color correlations of a matched image.
+pub fn generate_noise(options: &NoiseOptions) -> Result<DynamicImage> {
+    let (width, height) = (options.width as usize, options.height as usize);
+    if width == 0 || height == 0 {
+        anyhow::bail!("Noise size must be non-zero, got {}x{}", width, height);
+    }
+    let channel_count = if options.grayscale { 1 } else { 3 };
+    let fft = Fft2d::new(height, width);
+
+    let amplitudes = match &options.spectrum {
+        NoiseSpectrum::PowerLaw(alpha) => vec![power_law_amplitude(height, width, *alpha as f64); channel_count],
+        NoiseSpectrum::Image(path) => {
+            let reference = image::open(path)
+                .with_context(|| format!("Failed to open noise reference: {}", path.display()))?;
+            let reference = if reference.dimensions() != (options.width, options.height) {
+                reference.resize_exact(options.width, options.height, FilterType::Lanczos3)
+            } else {
+                reference
+            };
+            image_channels(&reference, options.grayscale)
+                .par_iter()
+                .map(|channel| Ok(fft.forward(channel)?.iter().map(|c| c.norm()).collect()))
+                .collect::<Result<Vec<Vec<f64>>>>()?
+        }
+    };
+
+    let mut rng = if let Some(seed) = options.seed {
+        StdRng::seed_from_u64(seed)
+    } else {
+        StdRng::from_os_rng()
+    };
+    let shared_phase = (options.phase_mode == Some(PhaseMode::Shared))
+        .then(|| hermitian_phase(&mut rng, height, width));
+    let spectra: Vec<Vec<Complex64>> = amplitudes
+        .iter()
+        .map(|amplitude| {
+            let phase = shared_phase
+                .clone()
+                .unwrap_or_else(|| hermitian_phase(&mut rng, height, width));
+            amplitude
+                .iter()
+                .zip(&phase)
+                .map(|(&amplitude, &phase)| Complex64::from_polar(amplitude, phase))
+                .collect()
+        })
+        .collect();
+
+    let mut channels = spectra
+        .par_iter()
+        .map(|spectrum| fft.inverse(spectrum))
+        .collect::<Result<Vec<_>>>()?;
+    if let NoiseSpectrum::PowerLaw(_) = options.spectrum {
+        for channel in channels.iter_mut() {
+            set_contrast(channel, options.contrast as f64);
+        }
+    }
+    Ok(to_image(&channels))
+}
+
+/// Amplitude `1 / f^alpha` of every coefficient, with `f` in cycles per image. The DC term is
+/// zero; the mean is set afterwards.
+fn power_law_amplitude(height: usize, width: usize, alpha: f64) -> Vec<f64> {
+    let grid = FrequencyGrid::new(height, width, (height, width), &FrequencyUnits::CyclesPerImage);
+    let mut amplitude = Vec::with_capacity(height * width);
+    for y in 0..height {
+        for x in 0..width {
+            let f = grid.radius(y, x);
+            amplitude.push(if f > 0.0 { f.powf(-alpha) } else { 0.0 });
+        }
+    }
+    amplitude
+}
+
+/// Random phase for every coefficient of a `height x width` spectrum, odd-symmetric so that
+/// the inverse transform is real.
+pub(crate) fn hermitian_phase(rng: &mut impl Rng, height: usize, width: usize) -> Vec<f64> {
+    let mut phase = random_phase_field(rng, height, width);
+    for y in 0..height {
+        for x in 0..width {
+            let (sym_y, sym_x) = conjugate_index(y, x, height, width);
+            if y > sym_y || (y == sym_y && x > sym_x) {
+                phase[y * width + x] = -phase[sym_y * width + sym_x];
+            }
+        }
+    }
+    phase
+}
+
+/// Scales `channel` to mean 0.5 and standard deviation `contrast`.
+fn set_contrast(channel: &mut Array2<f64>, contrast: f64) {
+    let mean = channel.mean().unwrap_or(0.0);
+    let std = channel.std(0.0);
+    let scale = if std > 0.0 { contrast / std } else { 0.0 };
+    channel.mapv_inplace(|value| 0.5 + (value - mean) * scale);
+}
+
+/// One luminance channel or three RGB channels in `[0, 1]`.
+fn image_channels(image: &DynamicImage, grayscale: bool) -> Vec<Array2<f64>> {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    if grayscale {
+        let gray = image.to_luma8();
+        return vec![Array2::from_shape_fn((height, width), |(y, x)| {
+            gray.get_pixel(x as u32, y as u32)[0] as f64 / 255.0
+        })];
+    }
+    let rgb = image.to_rgb8();
+    (0..3)
+        .map(|c| Array2::from_shape_fn((height, width), |(y, x)| rgb.get_pixel(x as u32, y as u32)[c] as f64 / 255.0))
+        .collect()
+}
+
+fn to_image(channels: &[Array2<f64>]) -> DynamicImage {
+    let (height, width) = channels[0].dim();
+    let value = |c: usize, x: u32, y: u32| (channels[c][[y as usize, x as usize]].clamp(0.0, 1.0) * 255.0).round() as u8;
+    if channels.len() == 1 {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(width as u32, height as u32, |x, y| Luma([value(0, x, y)])))
+    } else {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width as u32, height as u32, |x, y| {
+            Rgb([value(0, x, y), value(1, x, y), value(2, x, y)])
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn power_law_amplitude_falls_off_as_one_over_f_to_the_alpha() {
+        let (height, width) = (32, 32);
+        let amplitude = power_law_amplitude(height, width, 1.5);
+        assert_eq!(amplitude[0], 0.0);
+        // 2 and 8 cycles per image along the horizontal axis
+        assert!((amplitude[2] / amplitude[8] - 4f64.powf(1.5)).abs() < 1e-9);
+        assert!((amplitude[2 * width] - amplitude[2]).abs() < 1e-12);
+    }
+
+    #[test]
+    fn hermitian_phase_is_odd_symmetric() {
+        let (height, width) = (10, 13);
+        let phase = hermitian_phase(&mut StdRng::seed_from_u64(2), height, width);
+        for y in 0..height {
+            for x in 0..width {
+                let (sym_y, sym_x) = conjugate_index(y, x, height, width);
+                assert_eq!(phase[y * width + x], -phase[sym_y * width + sym_x]);
+            }
+        }
+    }
+
+    #[test]
+    fn power_law_noise_has_the_requested_statistics() {
+        let options = NoiseOptions { width: 64, height: 48, contrast: 0.1, seed: Some(4), ..NoiseOptions::default() };
+        let noise = generate_noise(&options).unwrap().to_luma8();
+        let values: Vec<f64> = noise.pixels().map(|p| p[0] as f64 / 255.0).collect();
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let std = (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64).sqrt();
+        assert!((mean - 0.5).abs() < 0.01);
+        assert!((std - 0.1).abs() < 0.01);
+
+        assert_eq!(generate_noise(&options).unwrap().to_luma8(), noise);
+        let reseeded = NoiseOptions { seed: Some(5), ..options };
+        assert_ne!(generate_noise(&reseeded).unwrap().to_luma8(), noise);
+    }
+}
@@ -0,0 +1,254 @@
+use anyhow::Context;
+use image::{DynamicImage, RgbaImage};
+use super::mask::RegionMask;
+use super::scrambler::ScrambleOutput;
+use super::types::{HistogramTarget, NormalizationMethod, NormalizationOptions, ScrambleOptions};
+use crate::Result;
+
+const HISTOGRAM_BINS: usize = 256;
+
+/// Luminance statistics of an image, in `[0, 1]`.
+#[derive(Debug, Clone)]
+pub struct LuminanceStats {
+    pub mean: f64,
+    /// RMS contrast
+    pub std: f64,
+    /// Fraction of pixels per 8-bit luminance level
+    pub histogram: Vec<f64>,
+}
+
+/// Statistics over the pixels of `image` where `mask` is at least 0.5, or over all pixels.
+pub fn luminance_stats(image: &DynamicImage, mask: Option<&RegionMask>) -> LuminanceStats {
+    let rgba = image.to_rgba8();
+    let luminance = luminance(&rgba);
+    let selected = selected_pixels(&rgba, mask);
+    stats_of(selected.iter().map(|&idx| luminance[idx]))
+}
+
+/// Average of several images' statistics, used as the target when normalizing a set.
+pub fn average_stats(stats: &[LuminanceStats]) -> LuminanceStats {
+    let count = stats.len().max(1) as f64;
+    let mut histogram = vec![0.0; HISTOGRAM_BINS];
+    for s in stats {
+        for (bin, value) in histogram.iter_mut().zip(&s.histogram) {
+            *bin += value / count;
+        }
+    }
+    LuminanceStats {
+        mean: stats.iter().map(|s| s.mean).sum::<f64>() / count,
+        std: stats.iter().map(|s| s.std).sum::<f64>() / count,
+        histogram,
+    }
+}
+
+/// Normalizes the luminance of `image` towards `reference` as described by `options`.
+/// Only pixels where `mask` is at least 0.5 are measured, and changes are scaled by the mask
+/// weight; without a mask the whole image is used.
+pub fn normalize_luminance(
+    image: &DynamicImage,
+    options: &NormalizationOptions,
+    reference: &LuminanceStats,
+    mask: Option<&RegionMask>,
+) -> Result<DynamicImage> {
+    let mut rgba = image.to_rgba8();
+    let luminance = luminance(&rgba);
+    let selected = selected_pixels(&rgba, mask);
+    if selected.is_empty() {
+        return Ok(image.clone());
+    }
+
+    let mut target = vec![0.0; selected.len()];
+    match &options.method {
+        NormalizationMethod::MeanStd { mean, std } => {
+            let current = stats_of(selected.iter().map(|&idx| luminance[idx]));
+            let target_mean = mean.map_or(reference.mean, |mean| mean as f64);
+            let target_std = std.map_or(reference.std, |std| std as f64);
+            for (value, &idx) in target.iter_mut().zip(&selected) {
+                let centered = luminance[idx] - current.mean;
+                let scaled = if current.std > 0.0 { centered / current.std * target_std } else { centered };
+                *value = scaled + target_mean;
+            }
+        }
+        NormalizationMethod::Histogram(histogram_target) => {
+            let histogram = match histogram_target {
+                HistogramTarget::Reference => reference.histogram.clone(),
+                HistogramTarget::Image(path) => {
+                    let reference_image = image::open(path)
+                        .with_context(|| format!("Failed to open histogram reference: {}", path.display()))?;
+                    luminance_stats(&reference_image, None).histogram
+                }
+            };
+            match_histogram(&luminance, &selected, &histogram, &mut target);
+        }
+    }
+
+    let width = rgba.width() as usize;
+    for (&idx, &new_luminance) in selected.iter().zip(&target) {
+        let weight = mask.map_or(1.0, |mask| mask.weight((idx % width) as u32, (idx / width) as u32)) as f64;
+        let delta = (new_luminance - luminance[idx]) * weight * 255.0;
+        let pixel = rgba.get_pixel_mut((idx % width) as u32, (idx / width) as u32);
+        for c in 0..3 {
+            pixel[c] = (pixel[c] as f64 + delta).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+
+    let normalized = DynamicImage::ImageRgba8(rgba);
+    let color = image.color();
+    Ok(match (color.has_color(), color.has_alpha()) {
+        (true, true) => normalized,
+        (true, false) => DynamicImage::ImageRgb8(normalized.to_rgb8()),
+        (false, true) => DynamicImage::ImageLumaA8(normalized.to_luma_alpha8()),
+        (false, false) => DynamicImage::ImageLuma8(normalized.to_luma8()),
+    })
+}
+
+/// Applies `options.normalization` to a scrambled output, with the unscrambled `original`
+/// as reference. `frame_index` selects the frame of a region mask sequence.
+pub fn normalize_output(
+    original: &DynamicImage,
+    output: ScrambleOutput,
+    options: &ScrambleOptions,
+    frame_index: usize,
+) -> Result<ScrambleOutput> {
+    let Some(norm_opts) = &options.normalization else {
+        return Ok(output);
+    };
+    let mask = if norm_opts.within_mask {
+        RegionMask::for_options(options, &output.face_regions, original.width(), original.height(), frame_index)?
+    } else {
+        None
+    };
+    let reference = luminance_stats(original, mask.as_ref());
+    let image = normalize_luminance(&output.image, norm_opts, &reference, mask.as_ref())?;
+    Ok(ScrambleOutput { image, ..output })
+}
+
+/// Rec. 601 luma of every pixel, in `[0, 1]`.
+fn luminance(rgba: &RgbaImage) -> Vec<f64> {
+    rgba.pixels()
+        .map(|p| (0.299 * p[0] as f64 + 0.587 * p[1] as f64 + 0.114 * p[2] as f64) / 255.0)
+        .collect()
+}
+
+fn selected_pixels(rgba: &RgbaImage, mask: Option<&RegionMask>) -> Vec<usize> {
+    let width = rgba.width() as usize;
+    (0..rgba.pixels().len())
+        .filter(|&idx| mask.is_none_or(|mask| mask.weight((idx % width) as u32, (idx / width) as u32) >= 0.5))
+        .collect()
+}
+
+fn stats_of(values: impl Iterator<Item = f64>) -> LuminanceStats {
+    let values: Vec<f64> = values.collect();
+    let mut histogram = vec![0.0; HISTOGRAM_BINS];
+    if values.is_empty() {
+        return LuminanceStats { mean: 0.0, std: 0.0, histogram };
+    }
+    let count = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / count;
+    let std = (values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / count).sqrt();
+    for value in &values {
+        histogram[luminance_bin(*value)] += 1.0 / count;
+    }
+    LuminanceStats { mean, std, histogram }
+}
+
+fn luminance_bin(value: f64) -> usize {
+    (value * 255.0).round().clamp(0.0, 255.0) as usize
+}
+
+/// Exact histogram specification: selected pixels are ranked by luminance (ties in pixel
+/// order) and the pixel at rank `k` gets the level at quantile `(k + 0.5) / n` of `histogram`.
+fn match_histogram(luminance: &[f64], selected: &[usize], histogram: &[f64], target: &mut [f64]) {
+    let total: f64 = histogram.iter().sum();
+    if total <= 0.0 {
+        for (value, &idx) in target.iter_mut().zip(selected) {
+            *value = luminance[idx];
+        }
+        return;
+    }
+    let mut cdf = Vec::with_capacity(histogram.len());
+    let mut sum = 0.0;
+    for value in histogram {
+        sum += value / total;
+        cdf.push(sum);
+    }
+
+    let mut order: Vec<usize> = (0..selected.len()).collect();
+    order.sort_by(|&a, &b| luminance[selected[a]].total_cmp(&luminance[selected[b]]));
+    let count = selected.len() as f64;
+    let mut level = 0;
+    for (rank, &position) in order.iter().enumerate() {
+        let quantile = (rank as f64 + 0.5) / count;
+        while level < cdf.len() - 1 && cdf[level] < quantile {
+            level += 1;
+        }
+        target[position] = level as f64 / 255.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma};
+    use crate::scramble::RegionShape;
+
+    fn gray(width: u32, height: u32, value: impl Fn(u32, u32) -> u8) -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(width, height, |x, y| Luma([value(x, y)])))
+    }
+
+    #[test]
+    fn mean_std_reaches_its_targets() {
+        let image = gray(32, 16, |x, y| (x * 5 + y * 3) as u8);
+        let options = NormalizationOptions {
+            method: NormalizationMethod::MeanStd { mean: Some(0.5), std: Some(0.1) },
+            within_mask: false,
+        };
+        let normalized = normalize_luminance(&image, &options, &luminance_stats(&image, None), None).unwrap();
+        let stats = luminance_stats(&normalized, None);
+        assert!((stats.mean - 0.5).abs() < 1.0 / 255.0);
+        assert!((stats.std - 0.1).abs() < 1.0 / 255.0);
+    }
+
+    #[test]
+    fn histogram_matching_copies_the_reference_histogram() {
+        let image = gray(16, 16, |x, y| ((x * 16 + y) % 256) as u8);
+        let reference = luminance_stats(&gray(16, 16, |x, y| (x * x + y) as u8 / 2), None);
+        let options = NormalizationOptions {
+            method: NormalizationMethod::Histogram(HistogramTarget::Reference),
+            within_mask: false,
+        };
+        let normalized = normalize_luminance(&image, &options, &reference, None).unwrap();
+        for (a, b) in luminance_stats(&normalized, None).histogram.iter().zip(&reference.histogram) {
+            assert!((a - b).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn only_masked_pixels_change() {
+        let image = gray(16, 16, |x, y| (x * 8 + y * 4) as u8);
+        let shapes = [RegionShape::Rectangle { x: 4, y: 4, width: 8, height: 8 }];
+        let mask = RegionMask::from_shapes(&shapes, 16, 16, 0).unwrap();
+        let options = NormalizationOptions {
+            method: NormalizationMethod::MeanStd { mean: Some(0.8), std: None },
+            within_mask: true,
+        };
+        let reference = luminance_stats(&image, Some(&mask));
+        let normalized = normalize_luminance(&image, &options, &reference, Some(&mask)).unwrap();
+        assert!((luminance_stats(&normalized, Some(&mask)).mean - 0.8).abs() < 1.0 / 255.0);
+        for (x, y, pixel) in normalized.to_luma8().enumerate_pixels() {
+            if mask.weight(x, y) == 0.0 {
+                assert_eq!(pixel[0], image.to_luma8().get_pixel(x, y)[0]);
+            }
+        }
+    }
+
+    #[test]
+    fn average_of_stats() {
+        let dark = luminance_stats(&gray(4, 4, |_, _| 0), None);
+        let bright = luminance_stats(&gray(4, 4, |_, _| 255), None);
+        let average = average_stats(&[dark, bright]);
+        assert!((average.mean - 0.5).abs() < 1e-12);
+        assert!((average.histogram[0] - 0.5).abs() < 1e-12);
+        assert!((average.histogram[255] - 0.5).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,153 @@
+use image::DynamicImage;
+use super::types::{PipelineStep, ScrambleOptions, ScrambleType, StepSeed};
+use super::scrambler::{create_scrambler, Scrambler};
+use crate::Result;
+
+/// Applies a chain of scramblers in order, feeding each output into the next step.
+pub struct PipelineScrambler {
+    stages: Vec<Box<dyn Scrambler>>,
+}
+
+impl PipelineScrambler {
+    /// Builds one scrambler per step. Step seeds are resolved from `seed` according
+    /// to each step's `StepSeed`, so a seeded pipeline is reproducible while its steps
+    /// still receive different random streams.
+    pub fn new(steps: &[PipelineStep], seed: Option<u64>, width: u32, height: u32) -> Self {
+        let stages = steps
+            .iter()
+            .enumerate()
+            .map(|(index, step)| {
+                let step_options = ScrambleOptions {
+                    scramble_type: step_scramble_type(step),
+                    intensity: step.intensity,
+                    seed: step_seed(&step.seed, seed, index),
+                    face_detection: None,
+                    region_mask: None,
+                    color_space: None,
+                    normalization: None,
+                };
+                create_scrambler(&step_options, width, height)
+            })
+            .collect();
+        Self { stages }
+    }
+
+    /// Builds a pipeline from already constructed scramblers, e.g. custom `Scrambler` implementations.
+    pub fn from_scramblers(stages: Vec<Box<dyn Scrambler>>) -> Self {
+        Self { stages }
+    }
+}
+
+impl Scrambler for PipelineScrambler {
+    fn scramble(&mut self, image: &DynamicImage) -> Result<DynamicImage> {
+        let mut current = image.clone();
+        for stage in self.stages.iter_mut() {
+            current = stage.scramble(&current)?;
+        }
+        Ok(current)
+    }
+}
+
+/// The scramble type of `step`, with `step.intensity` copied into the options of the
+/// types that carry their own intensity.
+fn step_scramble_type(step: &PipelineStep) -> ScrambleType {
+    let mut scramble_type = step.scramble_type.clone();
+    match &mut scramble_type {
+        ScrambleType::Fourier(fourier_opts) => fourier_opts.intensity = step.intensity,
+        ScrambleType::Wavelet(wavelet_opts) => wavelet_opts.intensity = step.intensity,
+        _ => {}
+    }
+    scramble_type
+}
+
+/// Resolves the seed of the step at `index` from the pipeline seed.
+fn step_seed(step_seed: &StepSeed, pipeline_seed: Option<u64>, index: usize) -> Option<u64> {
+    match step_seed {
+        StepSeed::Derived => pipeline_seed.map(|seed| derive_seed(seed, index as u64)),
+        StepSeed::Fixed(seed) => Some(*seed),
+        StepSeed::Random => None,
+    }
+}
+
+/// Replaces every `StepSeed::Random` in `steps`, including those of nested pipelines, with a
+/// freshly drawn `StepSeed::Fixed`, so that the steps can be recorded and replayed.
+/// Derived seeds are left as they are, since the pipeline seed determines them.
+/// Returns the indices (see `for_each_step`) of the replaced steps.
+pub fn resolve_step_seeds(steps: &mut [PipelineStep]) -> Vec<usize> {
+    let mut resolved = Vec::new();
+    for_each_step(steps, &mut 0, &mut |index, step| {
+        if let StepSeed::Random = step.seed {
+            step.seed = StepSeed::Fixed(rand::random());
+            resolved.push(index);
+        }
+    });
+    resolved
+}
+
+/// Gives the steps at `indices` (as returned by `resolve_step_seeds`) their seed for video frame
+/// `frame_index`: `StepSeed::Fixed(seed)` becomes `StepSeed::Fixed(derive_seed(seed, frame_index))`.
+pub fn derive_step_seeds(steps: &mut [PipelineStep], indices: &[usize], frame_index: u64) {
+    for_each_step(steps, &mut 0, &mut |index, step| {
+        if let StepSeed::Fixed(seed) = step.seed {
+            if indices.contains(&index) {
+                step.seed = StepSeed::Fixed(derive_seed(seed, frame_index));
+            }
+        }
+    });
+}
+
+/// Calls `f` for every step of `steps` and of their nested pipelines, depth first, together with
+/// the step's index in that order (a nested pipeline's steps follow the step holding it).
+fn for_each_step(steps: &mut [PipelineStep], next_index: &mut usize, f: &mut impl FnMut(usize, &mut PipelineStep)) {
+    for step in steps.iter_mut() {
+        f(*next_index, step);
+        *next_index += 1;
+        if let ScrambleType::Pipeline(nested) = &mut step.scramble_type {
+            for_each_step(nested, next_index, f);
+        }
+    }
+}
+
+/// Mixes `seed` and `salt` into a new, well distributed seed (SplitMix64 finalizer).
+/// The same inputs always give the same output, on every platform.
+pub fn derive_seed(seed: u64, salt: u64) -> u64 {
+    let mut z = seed
+        .wrapping_add(salt.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scramble::{FourierOptions, WaveletOptions};
+
+    #[test]
+    fn step_intensity_reaches_nested_options() {
+        let fourier = PipelineStep {
+            scramble_type: ScrambleType::Fourier(FourierOptions { intensity: 1.0, ..FourierOptions::default() }),
+            intensity: 0.25,
+            seed: StepSeed::Derived,
+        };
+        let ScrambleType::Fourier(fourier_opts) = step_scramble_type(&fourier) else { panic!("not Fourier") };
+        assert_eq!(fourier_opts.intensity, 0.25);
+
+        let wavelet = PipelineStep {
+            scramble_type: ScrambleType::Wavelet(WaveletOptions::default()),
+            intensity: 0.5,
+            seed: StepSeed::Derived,
+        };
+        let ScrambleType::Wavelet(wavelet_opts) = step_scramble_type(&wavelet) else { panic!("not Wavelet") };
+        assert_eq!(wavelet_opts.intensity, 0.5);
+    }
+
+    #[test]
+    fn derived_step_seeds_differ_and_repeat() {
+        let first = step_seed(&StepSeed::Derived, Some(9), 0);
+        assert_ne!(first, step_seed(&StepSeed::Derived, Some(9), 1));
+        assert_eq!(first, step_seed(&StepSeed::Derived, Some(9), 0));
+        assert_eq!(step_seed(&StepSeed::Derived, None, 0), None);
+        assert_eq!(step_seed(&StepSeed::Fixed(4), Some(9), 0), Some(4));
+    }
+}
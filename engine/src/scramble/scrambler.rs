@@ -0,0 +1,155 @@
+use image::{imageops, DynamicImage, GenericImageView};
+use face_detection::{detect_face_regions, load_face_detector, FaceRegion};
+use super::types::{BackgroundMode, FaceDetectionOptions, ScrambleOptions, ScrambleType};
+use super::mask::{composite_with_mask, RegionMask};
+use super::normalize::normalize_output;
+use super::{
+    BlockScrambler, BlurScrambler, ColorSpaceScrambler, DiffeomorphicScrambler, FourierScrambler, PipelineScrambler,
+    PixelScrambler, WaveletScrambler,
+};
+use crate::Result;
+
+/// Common interface implemented by every scrambling method.
+///
+/// Only `scramble` is required. Region handling and face detection are provided
+/// on top of it, so a custom scrambler plugs into batch, base64 and video
+/// processing by implementing this trait alone.
+pub trait Scrambler: Send {
+    /// Scrambles the full image.
+    fn scramble(&mut self, image: &DynamicImage) -> Result<DynamicImage>;
+
+    /// Scrambles the pixels inside `region` and returns the scrambled region
+    /// (sized `region.dimensions()`), not the full image.
+    fn scramble_region(&mut self, image: &DynamicImage, region: &FaceRegion) -> Result<DynamicImage> {
+        let (region_width, region_height) = region.dimensions();
+        let sub_image = image.crop_imm(region.x1, region.y1, region_width, region_height);
+        self.scramble(&sub_image)
+    }
+
+    /// Scrambles each region of `mask` separately and blends the result into the image
+    /// by mask weight, according to `background_mode`.
+    fn scramble_regions(
+        &mut self,
+        image: &DynamicImage,
+        mask: &RegionMask,
+        background_mode: &BackgroundMode,
+    ) -> Result<DynamicImage> {
+        let original = image.to_rgba8();
+        let mut foreground = original.clone();
+        for region in mask.regions() {
+            let processed = self.scramble_region(image, region)?.to_rgba8();
+            imageops::replace(&mut foreground, &processed, region.x1 as i64, region.y1 as i64);
+        }
+        Ok(DynamicImage::ImageRgba8(composite_with_mask(&original, &foreground, mask, background_mode)))
+    }
+
+    /// Scrambles only the given face regions, shaped and blended as `face_opts` describes.
+    fn scramble_faces(
+        &mut self,
+        image: &DynamicImage,
+        face_regions: &[FaceRegion],
+        face_opts: &FaceDetectionOptions,
+    ) -> Result<DynamicImage> {
+        let (width, height) = image.dimensions();
+        let mask = RegionMask::from_face_detections(face_regions, width, height, face_opts);
+        self.scramble_regions(image, &mask, &face_opts.background_mode)
+    }
+
+    /// Detects faces and scrambles only the detected regions.
+    fn scramble_with_face_detection(
+        &mut self,
+        image: &DynamicImage,
+        face_opts: &FaceDetectionOptions,
+    ) -> Result<DynamicImage> {
+        let face_regions = detect_faces(image, face_opts)?;
+        self.scramble_faces(image, &face_regions, face_opts)
+    }
+}
+
+/// A scrambled image together with the face regions it was restricted to, if any.
+pub struct ScrambleOutput {
+    pub image: DynamicImage,
+    pub face_regions: Vec<FaceRegion>,
+}
+
+/// Runs the face detector with the thresholds of `face_opts`.
+pub fn detect_faces(image: &DynamicImage, face_opts: &FaceDetectionOptions) -> Result<Vec<FaceRegion>> {
+    let session = load_face_detector(None)?;
+    detect_face_regions(
+        image,
+        session,
+        face_opts.confidence_threshold,
+        Some(face_opts.expansion_factor),
+    )
+}
+
+/// Builds the scrambler described by `options`.
+/// `width` and `height` are used to pre-plan size dependent state such as FFTs.
+pub fn create_scrambler(options: &ScrambleOptions, width: u32, height: u32) -> Box<dyn Scrambler> {
+    let scrambler: Box<dyn Scrambler> = match &options.scramble_type {
+        ScrambleType::Pixel => Box::new(PixelScrambler::new(options.intensity, options.seed)),
+        ScrambleType::Fourier(fourier_opts) => Box::new(FourierScrambler::new(
+            width as usize,
+            height as usize,
+            fourier_opts.clone(),
+            options.seed,
+        )),
+        ScrambleType::Block(block_opts) => Box::new(BlockScrambler::new(block_opts.clone(), options.seed)),
+        ScrambleType::Blur(blur_opts) => Box::new(BlurScrambler::new(blur_opts.clone())),
+        ScrambleType::Diffeomorphic(diff_opts) => {
+            Box::new(DiffeomorphicScrambler::new(diff_opts.clone(), options.seed))
+        }
+        ScrambleType::Wavelet(wavelet_opts) => Box::new(WaveletScrambler::new(wavelet_opts.clone(), options.seed)),
+        ScrambleType::Pipeline(steps) => Box::new(PipelineScrambler::new(steps, options.seed, width, height)),
+    };
+    match &options.color_space {
+        Some(color_opts) => Box::new(ColorSpaceScrambler::new(scrambler, color_opts.clone())),
+        None => scrambler,
+    }
+}
+
+/// Applies `scrambler` to `image`, restricted to detected faces or to the user-defined
+/// region mask when `options` asks for it.
+pub fn apply_scrambler(
+    scrambler: &mut dyn Scrambler,
+    image: &DynamicImage,
+    options: &ScrambleOptions,
+) -> Result<DynamicImage> {
+    Ok(apply_scrambler_with_regions(scrambler, image, options)?.image)
+}
+
+/// Same as `apply_scrambler`, but also returns the detected face regions.
+/// Luminance normalization, if requested, is applied to the result.
+pub fn apply_scrambler_with_regions(
+    scrambler: &mut dyn Scrambler,
+    image: &DynamicImage,
+    options: &ScrambleOptions,
+) -> Result<ScrambleOutput> {
+    let output = match (&options.face_detection, &options.region_mask) {
+        (Some(_), Some(_)) => return Err(anyhow::anyhow!("Face detection and region masks cannot be used together")),
+        (Some(face_opts), None) => {
+            let face_regions = detect_faces(image, face_opts)?;
+            let image = scrambler.scramble_faces(image, &face_regions, face_opts)?;
+            ScrambleOutput { image, face_regions }
+        }
+        (None, Some(mask_opts)) => {
+            let mask = RegionMask::from_options(mask_opts, image.width(), image.height(), 0)?;
+            let image = scrambler.scramble_regions(image, &mask, &mask_opts.background_mode)?;
+            ScrambleOutput { image, face_regions: Vec::new() }
+        }
+        (None, None) => ScrambleOutput { image: scrambler.scramble(image)?, face_regions: Vec::new() },
+    };
+    normalize_output(image, output, options, 0)
+}
+
+/// Scrambles `image` as described by `options`.
+/// This is the single dispatch point used by batch, base64 and video processing.
+pub fn scramble_image(image: &DynamicImage, options: &ScrambleOptions) -> Result<DynamicImage> {
+    Ok(scramble_image_with_regions(image, options)?.image)
+}
+
+/// Same as `scramble_image`, but also returns the detected face regions.
+pub fn scramble_image_with_regions(image: &DynamicImage, options: &ScrambleOptions) -> Result<ScrambleOutput> {
+    let mut scrambler = create_scrambler(options, image.width(), image.height());
+    apply_scrambler_with_regions(scrambler.as_mut(), image, options)
+}
@@ -8,6 +8,9 @@ pub enum ScrambleType {
     Block(BlockOptions),
     Blur(BlurOptions),
     Diffeomorphic(DiffeomorphicOptions),
+    Wavelet(WaveletOptions),
+    /// Several scramble types applied one after another
+    Pipeline(Vec<PipelineStep>),
 }
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum BackgroundMode {
@@ -21,6 +24,74 @@ pub struct ScrambleOptions {
     pub intensity: f32,
     pub seed: Option<u64>,
     pub face_detection: Option<FaceDetectionOptions>,
+    pub region_mask: Option<RegionMaskOptions>,
+    /// Color space to scramble in. `None` scrambles the sRGB channels directly.
+    pub color_space: Option<ColorSpaceOptions>,
+    /// Luminance normalization of the scrambled image, relative to the unscrambled input.
+    pub normalization: Option<NormalizationOptions>,
+}
+
+/// Luminance normalization applied after scrambling (SHINE-style lumMatch / histMatch).
+/// Color images are adjusted by adding the luminance change to every channel, which keeps chroma.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NormalizationOptions {
+    pub method: NormalizationMethod,
+    /// Measure and adjust only the scrambled regions (face detections or region mask)
+    pub within_mask: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum NormalizationMethod {
+    /// Match mean luminance and RMS contrast (standard deviation), both in `[0, 1]`.
+    /// `None` keeps the reference value: the unscrambled input for a single image,
+    /// the set average when normalizing a batch.
+    MeanStd {
+        mean: Option<f32>,
+        std: Option<f32>,
+    },
+    /// Exact histogram matching of luminance
+    Histogram(HistogramTarget),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum HistogramTarget {
+    Reference,       // The unscrambled input for a single image, the average histogram when normalizing a batch
+    Image(PathBuf),  // The luminance histogram of this image
+}
+
+/// Color space a scrambler works in, and which of its channels are scrambled.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ColorSpaceOptions {
+    pub space: ColorSpace,
+    /// Which of the three channels of `space` are scrambled, in order (e.g. L*, a*, b*).
+    /// Unselected channels keep their original values.
+    pub channels: [bool; 3],
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum ColorSpace {
+    Rgb,    // sRGB, same as no color space
+    Lab,    // CIELAB (D65): L*, a*, b*
+    YCbCr,  // Full-range BT.601 luma and chroma
+    Hsv,    // Hue, saturation, value; hue wraps around, so scrambling it mixes unrelated hues
+}
+
+/// One step of a `ScrambleType::Pipeline`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PipelineStep {
+    pub scramble_type: ScrambleType,
+    /// Intensity of the step; replaces the nested `intensity` of Fourier and Wavelet options.
+    /// Block, Blur, Diffeomorphic and Pipeline steps have no intensity and ignore it.
+    pub intensity: f32,
+    pub seed: StepSeed,
+}
+
+/// How a pipeline step obtains its random seed.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum StepSeed {
+    Derived,      // Derived from the pipeline seed and the step index (random if the pipeline seed is None)
+    Fixed(u64),   // Always use this seed, regardless of the pipeline seed
+    Random,       // Fresh random seed every run (and every frame of a video)
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -31,7 +102,123 @@ pub struct FourierOptions {
     pub padding_mode: PaddingMode,
     pub intensity: f32,
     pub grayscale: bool,
+    /// What to do with the alpha channel of transparent images. `None` behaves like `AlphaPolicy::PassThrough`.
+    pub alpha_policy: Option<AlphaPolicy>,
+    /// How random phases are assigned to the color channels. `None` behaves like `PhaseMode::Independent`.
+    pub phase_mode: Option<PhaseMode>,
+    /// Shape of the `frequency_range` filter and the units of its cutoffs.
+    /// `None` is an ideal (brick-wall) filter with cutoffs as fractions of Nyquist.
+    pub filter: Option<FilterOptions>,
+    /// Frequencies whose phase and magnitude are scrambled, using the same `filter` shape and units.
+    /// Frequencies outside it keep their original coefficients. `None` scrambles every frequency
+    /// that passes `frequency_range`.
+    pub scramble_band: Option<FrequencyRange>,
+    /// Orientation band, combined with the radial `frequency_range` and/or `scramble_band`.
+    pub orientation: Option<OrientationOptions>,
+    /// Apodization window applied to the image before padding, tapering it towards its mean
+    /// luminance at the edges. `None` leaves the image unwindowed.
+    pub window: Option<WindowFunction>,
+    /// Scrambles inside overlapping windows instead of over the whole image. `None` scrambles globally.
+    pub local: Option<LocalPhaseOptions>,
+}
+
+/// Local scrambling with a short-time Fourier transform: the image is cut into square windows
+/// that overlap by half, each window is tapered, transformed and scrambled on its own, and the
+/// windows are overlap-added back. Global layout is kept while structure smaller than a window
+/// is destroyed. Frequency bands refer to the window, so `FrequencyUnits::CyclesPerImage` means
+/// cycles per window. The image borders are extended by reflection; `padding_mode` and
+/// `window` do not apply.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LocalPhaseOptions {
+    /// Window side in pixels, rounded up to an even number
+    pub window_size: u32,
 }
+
+/// Selects image structure by orientation. Angles are in degrees, counter-clockwise, and refer
+/// to the orientation of edges in the image: 0 is horizontal structure (energy along the
+/// vertical frequency axis), 90 is vertical structure. Orientations wrap around at 180.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrientationOptions {
+    pub center: f32,
+    /// Orientations within this many degrees of `center` pass fully
+    pub half_width: f32,
+    /// Width of the cosine taper beyond `half_width`, in degrees. 0 is a hard edge.
+    pub transition: f32,
+    pub target: OrientationTarget,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum OrientationTarget {
+    Filter,    // Remove energy outside the orientation band
+    Scramble,  // Only scramble within the orientation band, keep the rest intact
+    Both,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum PhaseMode {
+    Independent,  // Every channel gets its own random phase field
+    Shared,       // One random phase field is added to every channel, so colors are preserved
+}
+
+/// Weights of a phase/amplitude swap between two images.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SpectrumSwapOptions {
+    /// 1.0 takes the amplitude spectrum entirely from the amplitude image, 0.0 from the phase image
+    pub amplitude_weight: f32,
+    /// 1.0 takes the phase spectrum entirely from the phase image, 0.0 from the amplitude image
+    pub phase_weight: f32,
+}
+
+/// Noise stimulus produced by `generate_noise`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NoiseOptions {
+    pub width: u32,
+    pub height: u32,
+    pub spectrum: NoiseSpectrum,
+    pub grayscale: bool,
+    /// RMS contrast around mid-gray of `NoiseSpectrum::PowerLaw` noise. Matched noise keeps the
+    /// mean luminance and contrast of its reference.
+    pub contrast: f32,
+    /// How random phases are assigned to the color channels. `None` behaves like `PhaseMode::Independent`.
+    pub phase_mode: Option<PhaseMode>,
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum NoiseSpectrum {
+    PowerLaw(f32),   // Amplitude falls off as 1/f^alpha: 0.0 is white noise, 1.0 pink noise
+    Image(PathBuf),  // Amplitude spectrum of this image, resized to the noise size
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum AlphaPolicy {
+    Discard,      // Output an opaque image
+    PassThrough,  // Scramble color, keep the original alpha
+    OpaqueOnly,   // Fill transparent areas with the mean opaque color before scrambling, keep the original alpha
+    Scramble,     // Scramble alpha as an additional channel
+}
+/// How `FrequencyRange` is applied to the spectrum.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FilterOptions {
+    pub shape: FilterShape,
+    pub units: FrequencyUnits,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum FilterShape {
+    Ideal,              // Brick-wall cutoff
+    Butterworth(u32),   // Filter order; gain is 0.5 at the cutoff
+    Gaussian,           // Gain exp(-f^2 / (2 * cutoff^2)), about 0.61 at the cutoff
+    RaisedCosine(f32),  // Cosine transition of this width (in `units`) centered on the cutoff
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum FrequencyUnits {
+    Nyquist,                                     // Fraction of the Nyquist frequency along each axis (0.0 - 1.0)
+    CyclesPerImage,                              // Cycles across the longer image side
+    CyclesPerDegree { pixels_per_degree: f32 },  // Cycles per degree of visual angle at the given viewing geometry
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum FrequencyRange {
     All,
@@ -55,11 +242,72 @@ pub enum PaddingMode {
     Reflect,
     Wrap,
 }
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum WindowFunction {
+    Hann,
+    Tukey(f32),     // Fraction of each axis that is tapered, 0.0 (no taper) to 1.0 (Hann)
+    Gaussian(f32),  // Standard deviation as a fraction of half the axis length
+}
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FaceDetectionOptions {
     pub confidence_threshold: f32,
     pub expansion_factor: f32,
     pub background_mode: BackgroundMode,
+    pub soft_mask: Option<SoftMaskOptions>,
+    /// Scramble an oval aperture around each face instead of its bounding box
+    pub face_ellipse: Option<FaceEllipseOptions>,
+}
+
+/// Oval face aperture centered on each detection.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FaceEllipseOptions {
+    /// Ellipse height divided by its width
+    pub aspect_ratio: f32,
+    /// Ellipse width relative to the detection width
+    pub scale: f32,
+}
+
+/// User-defined regions to scramble, as an alternative to face detection.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RegionMaskOptions {
+    pub regions: Vec<RegionShape>,
+    pub background_mode: BackgroundMode,
+    pub soft_mask: Option<SoftMaskOptions>,
+}
+
+/// Soft edges used when blending scrambled regions into the original image.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SoftMaskOptions {
+    /// Width of the transition between scrambled and original pixels, in pixels
+    pub feather_width: f32,
+    pub falloff: MaskFalloff,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum MaskFalloff {
+    Gaussian,    // Gaussian-blurred mask edge
+    Elliptical,  // Fade from each region's center towards the ellipse inscribed in its bounding box
+}
+
+/// A region in pixel coordinates. Overlapping regions are combined (union).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum RegionShape {
+    Rectangle {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+    Ellipse {
+        center_x: f32,
+        center_y: f32,
+        radius_x: f32,
+        radius_y: f32,
+    },
+    Polygon(Vec<(f32, f32)>),  // vertices in order, closed implicitly
+    MaskImage(PathBuf),        // grayscale image, white = scramble; resized to the image if needed
+    MaskSequence(PathBuf),     // directory of mask images, one per video frame in file name order
 }
 
 impl Default for ScrambleOptions {
@@ -69,6 +317,18 @@ impl Default for ScrambleOptions {
             intensity: 0.5,
             seed: None,
             face_detection: None,
+            region_mask: None,
+            color_space: None,
+            normalization: None,
+        }
+    }
+}
+// Scramble only L*, keeping chroma
+impl Default for ColorSpaceOptions {
+    fn default() -> Self {
+        Self {
+            space: ColorSpace::Lab,
+            channels: [true, false, false],
         }
     }
 }
@@ -79,6 +339,33 @@ impl Default for FaceDetectionOptions {
             confidence_threshold: 0.7,
             expansion_factor: 1.0,
             background_mode: BackgroundMode::Include,
+            soft_mask: None,
+            face_ellipse: None,
+        }
+    }
+}
+impl Default for FaceEllipseOptions {
+    fn default() -> Self {
+        Self {
+            aspect_ratio: 1.35,
+            scale: 1.0,
+        }
+    }
+}
+impl Default for SoftMaskOptions {
+    fn default() -> Self {
+        Self {
+            feather_width: 10.0,
+            falloff: MaskFalloff::Gaussian,
+        }
+    }
+}
+impl Default for PipelineStep {
+    fn default() -> Self {
+        Self {
+            scramble_type: ScrambleType::Pixel,
+            intensity: 0.5,
+            seed: StepSeed::Derived,
         }
     }
 }
@@ -91,6 +378,34 @@ impl Default for FourierOptions {
             padding_mode: PaddingMode::Reflect,
             intensity: 1.0,
             grayscale: false,
+            alpha_policy: None,
+            phase_mode: None,
+            filter: None,
+            scramble_band: None,
+            orientation: None,
+            window: None,
+            local: None,
+        }
+    }
+}
+impl Default for SpectrumSwapOptions {
+    fn default() -> Self {
+        Self {
+            amplitude_weight: 1.0,
+            phase_weight: 1.0,
+        }
+    }
+}
+impl Default for NoiseOptions {
+    fn default() -> Self {
+        Self {
+            width: 256,
+            height: 256,
+            spectrum: NoiseSpectrum::PowerLaw(1.0),
+            grayscale: true,
+            contrast: 0.2,
+            phase_mode: None,
+            seed: None,
         }
     }
 }
@@ -137,6 +452,49 @@ impl Default for DiffeomorphicOptions {
     }
 }
 
+/// Scrambles the detail coefficients of a discrete wavelet transform, which localizes the
+/// scrambling in scale as well as in space.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WaveletOptions {
+    pub wavelet: Wavelet,
+    /// Number of decomposition levels, reduced if the image is too small for them
+    pub levels: u32,
+    /// First and last level scrambled (inclusive); level 1 holds the finest detail
+    pub level_range: (u32, u32),
+    /// Which detail orientations are scrambled: horizontal, vertical, diagonal
+    pub orientations: [bool; 3],
+    pub method: WaveletScrambleMethod,
+    pub intensity: f32,
+    pub grayscale: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum Wavelet {
+    Haar,
+    Daubechies(u32),  // Number of vanishing moments, 2 to 4 (1 is Haar)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum WaveletScrambleMethod {
+    Shuffle,      // Permute a fraction `intensity` of the coefficients within each subband
+    SignFlip,     // Flip each coefficient's sign with probability `intensity / 2`
+    RandomPhase,  // Randomize the Fourier phase of each subband, keeping its amplitude spectrum
+}
+
+impl Default for WaveletOptions {
+    fn default() -> Self {
+        Self {
+            wavelet: Wavelet::Daubechies(2),
+            levels: 4,
+            level_range: (1, 4),
+            orientations: [true; 3],
+            method: WaveletScrambleMethod::Shuffle,
+            intensity: 1.0,
+            grayscale: false,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TemporalCoherenceOptions {
     pub export_flow: bool,
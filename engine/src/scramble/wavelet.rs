@@ -0,0 +1,339 @@
+use std::ops::Range;
+use image::{DynamicImage, GenericImageView, GrayImage, Luma, Rgb, RgbImage, Rgba, RgbaImage};
+use ndarray::{s, Array2, ArrayViewMut2};
+use num_complex::Complex64;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rayon::prelude::*;
+use crate::Result;
+use super::fft::Fft2d;
+use super::fourier::reflect_index;
+use super::noise::hermitian_phase;
+use super::scrambler::Scrambler;
+use super::types::{Wavelet, WaveletOptions, WaveletScrambleMethod};
+
+/// Scrambles images in the wavelet domain.
+///
+/// Each channel is extended by reflection to a multiple of `2^levels` and decomposed with a
+/// periodized orthogonal wavelet transform. The detail subbands of the selected levels and
+/// orientations are scrambled, the approximation is kept, and the inverse transform
+/// reconstructs the image. Every channel gets the same random draws, which keeps the colors
+/// of the scrambled structure. Alpha is passed through unchanged.
+pub struct WaveletScrambler {
+    options: WaveletOptions,
+    rng: StdRng,
+}
+
+/// Random changes drawn for one subband and applied to it in every channel.
+enum SubbandPlan {
+    /// New position `targets[i]` receives the coefficient at `sources[i]`
+    Permutation { targets: Vec<usize>, sources: Vec<usize> },
+    Signs(Vec<f64>),
+    Phase { fft: Box<Fft2d>, offsets: Vec<f64> },
+}
+
+impl WaveletScrambler {
+    pub fn new(options: WaveletOptions, seed: Option<u64>) -> Self {
+        let rng = if let Some(seed) = seed {
+            StdRng::seed_from_u64(seed)
+        } else {
+            StdRng::from_os_rng()
+        };
+
+        Self {
+            options,
+            rng,
+        }
+    }
+
+    fn draw_plan(&mut self, height: usize, width: usize) -> SubbandPlan {
+        let intensity = (self.options.intensity as f64).clamp(0.0, 1.0);
+        match self.options.method {
+            WaveletScrambleMethod::Shuffle => {
+                let targets: Vec<usize> = (0..height * width)
+                    .filter(|_| self.rng.random::<f64>() < intensity)
+                    .collect();
+                let mut sources = targets.clone();
+                sources.shuffle(&mut self.rng);
+                SubbandPlan::Permutation { targets, sources }
+            }
+            WaveletScrambleMethod::SignFlip => SubbandPlan::Signs(
+                (0..height * width)
+                    .map(|_| if self.rng.random::<f64>() < intensity / 2.0 { -1.0 } else { 1.0 })
+                    .collect(),
+            ),
+            WaveletScrambleMethod::RandomPhase => {
+                let offsets = hermitian_phase(&mut self.rng, height, width)
+                    .into_iter()
+                    .map(|offset| offset * intensity)
+                    .collect();
+                SubbandPlan::Phase { fft: Box::new(Fft2d::new(height, width)), offsets }
+            }
+        }
+    }
+}
+
+impl SubbandPlan {
+    fn apply(&self, mut subband: ArrayViewMut2<f64>) -> Result<()> {
+        let width = subband.ncols();
+        match self {
+            SubbandPlan::Permutation { targets, sources } => {
+                let original = subband.to_owned();
+                for (&target, &source) in targets.iter().zip(sources) {
+                    subband[[target / width, target % width]] = original[[source / width, source % width]];
+                }
+            }
+            SubbandPlan::Signs(signs) => {
+                for (value, sign) in subband.iter_mut().zip(signs) {
+                    *value *= sign;
+                }
+            }
+            SubbandPlan::Phase { fft, offsets } => {
+                let mut spectrum = fft.forward(&subband.to_owned())?;
+                for (coefficient, &offset) in spectrum.iter_mut().zip(offsets) {
+                    *coefficient *= Complex64::from_polar(1.0, offset);
+                }
+                subband.assign(&fft.inverse(&spectrum)?);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Scrambler for WaveletScrambler {
+    fn scramble(&mut self, image: &DynamicImage) -> Result<DynamicImage> {
+        let filter = lowpass_filter(&self.options.wavelet)?;
+        let (width, height) = image.dimensions();
+        let (w, h) = (width as usize, height as usize);
+        // No more levels than halvings of the shorter side, and the coarsest level must still be
+        // at least as long as the filter
+        let mut levels = (self.options.levels as usize).min(w.min(h).max(1).ilog2() as usize);
+        while levels > 0 && (w.min(h) >> (levels - 1)) < filter.len() {
+            levels -= 1;
+        }
+        let block = 1 << levels;
+        let (padded_h, padded_w) = (h.div_ceil(block) * block, w.div_ceil(block) * block);
+
+        let rgba = image.to_rgba8();
+        let channels: Vec<Array2<f64>> = if self.options.grayscale {
+            let gray = image.to_luma8();
+            vec![Array2::from_shape_fn((h, w), |(y, x)| gray.get_pixel(x as u32, y as u32)[0] as f64 / 255.0)]
+        } else {
+            (0..3)
+                .map(|c| Array2::from_shape_fn((h, w), |(y, x)| rgba.get_pixel(x as u32, y as u32)[c] as f64 / 255.0))
+                .collect()
+        };
+        let mut coefficients: Vec<Array2<f64>> = channels
+            .par_iter()
+            .map(|channel| {
+                let mut padded = Array2::from_shape_fn((padded_h, padded_w), |(y, x)| {
+                    channel[[reflect_index(y, h), reflect_index(x, w)]]
+                });
+                forward(&mut padded, filter, levels);
+                padded
+            })
+            .collect();
+
+        let (first, last) = self.options.level_range;
+        for level in first.max(1) as usize..=(last as usize).min(levels) {
+            for (orientation, selected) in self.options.orientations.into_iter().enumerate() {
+                if !selected {
+                    continue;
+                }
+                let (rows, cols) = subband_region(padded_h, padded_w, level, orientation);
+                let plan = self.draw_plan(rows.len(), cols.len());
+                coefficients
+                    .par_iter_mut()
+                    .map(|channel| plan.apply(channel.slice_mut(s![rows.clone(), cols.clone()])))
+                    .collect::<Result<Vec<_>>>()?;
+            }
+        }
+
+        let channels: Vec<Array2<f64>> = coefficients
+            .into_par_iter()
+            .map(|mut channel| {
+                inverse(&mut channel, filter, levels);
+                channel
+            })
+            .collect();
+        let value = |c: usize, x: u32, y: u32| (channels[c][[y as usize, x as usize]].clamp(0.0, 1.0) * 255.0).round() as u8;
+        if self.options.grayscale {
+            return Ok(DynamicImage::ImageLuma8(GrayImage::from_fn(width, height, |x, y| Luma([value(0, x, y)]))));
+        }
+        if image.color().has_alpha() {
+            Ok(DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+                Rgba([value(0, x, y), value(1, x, y), value(2, x, y), rgba.get_pixel(x, y)[3]])
+            })))
+        } else {
+            Ok(DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+                Rgb([value(0, x, y), value(1, x, y), value(2, x, y)])
+            })))
+        }
+    }
+}
+
+/// Orthonormal low-pass decomposition filter.
+fn lowpass_filter(wavelet: &Wavelet) -> Result<&'static [f64]> {
+    const HAAR: [f64; 2] = [std::f64::consts::FRAC_1_SQRT_2, std::f64::consts::FRAC_1_SQRT_2];
+    const DB2: [f64; 4] = [0.48296291314469025, 0.836516303737469, 0.22414386804185735, -0.12940952255092145];
+    const DB3: [f64; 6] = [
+        0.3326705529509569, 0.8068915093133388, 0.4598775021193313,
+        -0.13501102001039084, -0.08544127388224149, 0.035226291882100656,
+    ];
+    const DB4: [f64; 8] = [
+        0.23037781330885523, 0.7148465705525415, 0.6308807679295904, -0.02798376941698385,
+        -0.18703481171888114, 0.030841381835986965, 0.032883011666982945, -0.010597401784997278,
+    ];
+    match wavelet {
+        Wavelet::Haar | Wavelet::Daubechies(1) => Ok(&HAAR),
+        Wavelet::Daubechies(2) => Ok(&DB2),
+        Wavelet::Daubechies(3) => Ok(&DB3),
+        Wavelet::Daubechies(4) => Ok(&DB4),
+        Wavelet::Daubechies(order) => anyhow::bail!("Daubechies wavelets of order {} are not supported, use 1 to 4", order),
+    }
+}
+
+/// Rows and columns of a detail subband in the transformed array. Orientation 0 is the
+/// horizontal detail (horizontal edges), 1 the vertical and 2 the diagonal detail.
+fn subband_region(height: usize, width: usize, level: usize, orientation: usize) -> (Range<usize>, Range<usize>) {
+    let (region_h, region_w) = (height >> (level - 1), width >> (level - 1));
+    let (half_h, half_w) = (region_h / 2, region_w / 2);
+    match orientation {
+        0 => (half_h..region_h, 0..half_w),
+        1 => (0..half_h, half_w..region_w),
+        _ => (half_h..region_h, half_w..region_w),
+    }
+}
+
+/// Multi-level 2D transform in place: each level transforms the rows and then the columns of
+/// the previous approximation, which ends up in the top-left quarter.
+fn forward(data: &mut Array2<f64>, filter: &[f64], levels: usize) {
+    let (height, width) = data.dim();
+    for level in 0..levels {
+        let (region_h, region_w) = (height >> level, width >> level);
+        let mut region = data.slice_mut(s![..region_h, ..region_w]);
+        for mut row in region.rows_mut() {
+            let mut values = row.to_vec();
+            analyze(&mut values, filter);
+            row.assign(&ndarray::ArrayView1::from(&values));
+        }
+        for mut col in region.columns_mut() {
+            let mut values = col.to_vec();
+            analyze(&mut values, filter);
+            col.assign(&ndarray::ArrayView1::from(&values));
+        }
+    }
+}
+
+fn inverse(data: &mut Array2<f64>, filter: &[f64], levels: usize) {
+    let (height, width) = data.dim();
+    for level in (0..levels).rev() {
+        let (region_h, region_w) = (height >> level, width >> level);
+        let mut region = data.slice_mut(s![..region_h, ..region_w]);
+        for mut col in region.columns_mut() {
+            let mut values = col.to_vec();
+            synthesize(&mut values, filter);
+            col.assign(&ndarray::ArrayView1::from(&values));
+        }
+        for mut row in region.rows_mut() {
+            let mut values = row.to_vec();
+            synthesize(&mut values, filter);
+            row.assign(&ndarray::ArrayView1::from(&values));
+        }
+    }
+}
+
+/// One level of the periodized transform of an even-length signal: approximation in the first
+/// half, detail in the second.
+fn analyze(values: &mut [f64], filter: &[f64]) {
+    let n = values.len();
+    let half = n / 2;
+    let last = filter.len() - 1;
+    let mut output = vec![0.0; n];
+    for i in 0..half {
+        let (mut approximation, mut detail) = (0.0, 0.0);
+        for (k, &h) in filter.iter().enumerate() {
+            let x = values[(2 * i + k) % n];
+            approximation += h * x;
+            detail += highpass(filter, last, k) * x;
+        }
+        output[i] = approximation;
+        output[half + i] = detail;
+    }
+    values.copy_from_slice(&output);
+}
+
+/// Inverse of `analyze`.
+fn synthesize(values: &mut [f64], filter: &[f64]) {
+    let n = values.len();
+    let half = n / 2;
+    let last = filter.len() - 1;
+    let mut output = vec![0.0; n];
+    for i in 0..half {
+        let (approximation, detail) = (values[i], values[half + i]);
+        for (k, &h) in filter.iter().enumerate() {
+            output[(2 * i + k) % n] += h * approximation + highpass(filter, last, k) * detail;
+        }
+    }
+    values.copy_from_slice(&output);
+}
+
+/// Tap `k` of the quadrature mirror high-pass filter.
+fn highpass(filter: &[f64], last: usize, k: usize) -> f64 {
+    if k.is_multiple_of(2) { filter[last - k] } else { -filter[last - k] }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAVELETS: [Wavelet; 5] = [
+        Wavelet::Haar,
+        Wavelet::Daubechies(1),
+        Wavelet::Daubechies(2),
+        Wavelet::Daubechies(3),
+        Wavelet::Daubechies(4),
+    ];
+
+    #[test]
+    fn inverse_restores_forward_input() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let input = Array2::from_shape_fn((48, 64), |_| rng.random::<f64>());
+        for wavelet in &WAVELETS {
+            let filter = lowpass_filter(wavelet).unwrap();
+            for levels in 1..=3 {
+                let mut data = input.clone();
+                forward(&mut data, filter, levels);
+                inverse(&mut data, filter, levels);
+                for (a, b) in input.iter().zip(data.iter()) {
+                    assert!((a - b).abs() < 1e-9, "{:?} at {} levels: {} != {}", wavelet, levels, a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn excessive_levels_are_clamped() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(20, 12, |x, y| {
+            Rgb([(x * 12) as u8, (y * 20) as u8, 128])
+        }));
+        let options = WaveletOptions {
+            levels: u32::MAX,
+            level_range: (1, u32::MAX),
+            intensity: 0.0,
+            ..WaveletOptions::default()
+        };
+        let output = WaveletScrambler::new(options, Some(1)).scramble(&image).unwrap().to_rgb8();
+        for (a, b) in image.to_rgb8().pixels().zip(output.pixels()) {
+            for (a, b) in a.0.iter().zip(b.0.iter()) {
+                assert!(a.abs_diff(*b) <= 1, "{} != {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn unsupported_order_is_rejected() {
+        assert!(lowpass_filter(&Wavelet::Daubechies(5)).is_err());
+    }
+}
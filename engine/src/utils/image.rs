@@ -2,7 +2,6 @@ use base64::{Engine as _, engine::general_purpose};
 use image::ImageFormat;
 use std::io::Cursor;
 use crate::Result;
-use crate::scramble::ScrambleType;
 
 /// Processes a base64-encoded image with the given scramble options
 /// 
@@ -28,58 +27,7 @@ pub fn process_base64_image(
     let img = image::load_from_memory(&image_bytes)
         .map_err(|e| anyhow::anyhow!("Failed to load image: {}", e))?;
 
-    let scrambled = match &options.scramble_type {
-        ScrambleType::Pixel => crate::scramble::scramble_pixels(&img, options)?,
-        ScrambleType::Fourier(fourier_opts) => {
-            let mut scrambler = crate::scramble::FourierScrambler::new(
-                img.width() as usize,
-                img.height() as usize,
-                fourier_opts.clone(),
-                options.seed,
-            );
-            
-            if let Some(face_opts) = &options.face_detection {
-                scrambler.scramble_with_face_detection(&img, face_opts)?
-            } else {
-                scrambler.scramble(&img)?
-            }
-        },
-        ScrambleType::Block(block_opts) => {
-            let mut scrambler = crate::scramble::BlockScrambler::new(
-                block_opts.clone(),
-                options.seed,
-            );
-            
-            if let Some(face_opts) = &options.face_detection {
-                scrambler.scramble_with_face_detection(&img, face_opts)?
-            } else {
-                scrambler.scramble(&img)?
-            }
-        },
-        ScrambleType::Blur(blur_opts) => {
-            let scrambler = crate::scramble::BlurScrambler::new(
-                blur_opts.clone(),
-            );
-
-            if let Some(face_opts) = &options.face_detection {
-                scrambler.scramble_with_face_detection(&img, face_opts)?
-            } else {
-                scrambler.scramble(&img)?
-            }
-        }
-        ScrambleType::Diffeomorphic(diff_opts) => {
-            let mut scrambler = crate::scramble::DiffeomorphicScrambler::new(
-                diff_opts.clone(),
-                options.seed,
-            );
-
-            if let Some(face_opts) = &options.face_detection {
-                scrambler.scramble_with_face_detection(&img, face_opts)?
-            } else {
-                scrambler.scramble(&img)?
-            }
-        }
-    };
+    let scrambled = crate::scramble::scramble_image(&img, options)?;
     let mut buffer = Cursor::new(Vec::new());
     scrambled
         .write_to(&mut buffer, ImageFormat::Png)
@@ -5,7 +5,9 @@ use video_processor::{VideoProcessor, VideoFrameExt};
 use image::{DynamicImage, RgbaImage};
 use serde::{Serialize, Deserialize};
 use log::info;
-use crate::scramble::{ScrambleType, ScrambleOptions, TemporalCoherenceOptions, FaceDetectionOptions};
+use crate::scramble::{ScrambleOptions, ScrambleOutput, TemporalCoherenceOptions, RegionMaskOptions, RegionShape};
+use crate::scramble::{RegionMask, ScrambleType, composite_with_mask, derive_seed, derive_step_seeds};
+use crate::manifest::{hash_file, resolve_seed_with_steps, write_sidecar, FrameRegions, RegionRecord, VideoManifest, ENGINE_VERSION};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct VideoProcessingOptions {
@@ -17,98 +19,53 @@ pub struct VideoProcessingOptions {
 
 /// Apply scrambling to a single frame based on the scramble options.
 /// When face detection is enabled, only face regions are scrambled (background stays intact).
-fn scramble_frame(image: &RgbaImage, scramble_options: &ScrambleOptions) -> Result<DynamicImage> {
-    let (width, height) = image.dimensions();
+/// When a region mask is set, `frame_index` selects the mask of a per-frame mask sequence.
+fn scramble_frame(image: &RgbaImage, scramble_options: &ScrambleOptions, frame_index: usize) -> Result<ScrambleOutput> {
     let dyn_image = DynamicImage::ImageRgba8(image.clone());
-
-    match &scramble_options.scramble_type {
-        ScrambleType::Pixel => {
-            crate::scramble::scramble_pixels(&dyn_image, scramble_options)
-        }
-        ScrambleType::Fourier(fourier_opts) => {
-            let mut scrambler = crate::scramble::FourierScrambler::new(
-                width as usize,
-                height as usize,
-                fourier_opts.clone(),
-                scramble_options.seed,
-            );
-            if let Some(face_opts) = &scramble_options.face_detection {
-                scrambler.scramble_with_face_detection(&dyn_image, face_opts)
-            } else {
-                scrambler.scramble(&dyn_image)
-            }
-        }
-        ScrambleType::Block(block_opts) => {
-            let mut scrambler = crate::scramble::BlockScrambler::new(
-                block_opts.clone(),
-                scramble_options.seed,
-            );
-            if let Some(face_opts) = &scramble_options.face_detection {
-                scrambler.scramble_with_face_detection(&dyn_image, face_opts)
-            } else {
-                scrambler.scramble(&dyn_image)
-            }
-        }
-        ScrambleType::Blur(blur_opts) => {
-            let scrambler = crate::scramble::BlurScrambler::new(blur_opts.clone());
-            if let Some(face_opts) = &scramble_options.face_detection {
-                scrambler.scramble_with_face_detection(&dyn_image, face_opts)
-            } else {
-                scrambler.scramble(&dyn_image)
-            }
-        }
-        ScrambleType::Diffeomorphic(diff_opts) => {
-            let mut scrambler = crate::scramble::DiffeomorphicScrambler::new(
-                diff_opts.clone(),
-                scramble_options.seed,
-            );
-            if let Some(face_opts) = &scramble_options.face_detection {
-                scrambler.scramble_with_face_detection(&dyn_image, face_opts)
-            } else {
-                scrambler.scramble(&dyn_image)
-            }
+    match &scramble_options.region_mask {
+        Some(mask_opts) if scramble_options.face_detection.is_none() => {
+            let (width, height) = image.dimensions();
+            let mask = RegionMask::from_options(mask_opts, width, height, frame_index)?;
+            let mut scrambler = crate::scramble::create_scrambler(scramble_options, width, height);
+            let image = scrambler.scramble_regions(&dyn_image, &mask, &mask_opts.background_mode)?;
+            let output = ScrambleOutput { image, face_regions: Vec::new() };
+            crate::scramble::normalize_output(&dyn_image, output, scramble_options, frame_index)
         }
+        _ => crate::scramble::scramble_image_with_regions(&dyn_image, scramble_options),
     }
 }
 
-/// Detect face regions on the given image. Returns bounding boxes.
-fn detect_faces(image: &RgbaImage, face_opts: &FaceDetectionOptions) -> Result<Vec<face_detection::FaceRegion>> {
-    let dyn_image = DynamicImage::ImageRgba8(image.clone());
-    let session = face_detection::load_face_detector(None)?;
-    face_detection::detect_face_regions(
-        &dyn_image,
-        session,
-        face_opts.confidence_threshold,
-        Some(face_opts.expansion_factor),
-    )
-}
-
-/// Build a boolean mask from face regions.
-fn regions_to_mask(regions: &[face_detection::FaceRegion], width: u32, height: u32) -> Vec<bool> {
-    let mut mask = vec![false; (width * height) as usize];
-    for region in regions {
-        for y in region.y1..region.y2.min(height) {
-            for x in region.x1..region.x2.min(width) {
-                mask[(y * width + x) as usize] = true;
-            }
-        }
+/// Options for one frame. Without a user-provided seed every frame gets its own seed
+/// derived from the run's effective seed, so frames differ but the run stays reproducible.
+/// The pipeline steps at `random_steps` (see `resolve_step_seeds`) always get a seed per frame.
+fn frame_options(
+    scramble_options: &ScrambleOptions,
+    per_frame_seeds: bool,
+    random_steps: &[usize],
+    frame_index: usize,
+) -> ScrambleOptions {
+    let mut options = scramble_options.clone();
+    if per_frame_seeds {
+        options.seed = scramble_options.seed.map(|seed| derive_seed(seed, frame_index as u64));
     }
-    mask
+    if let ScrambleType::Pipeline(steps) = &mut options.scramble_type {
+        derive_step_seeds(steps, random_steps, frame_index as u64);
+    }
+    options
 }
 
-/// Composite two frames using a mask.
-/// Pixels where mask=true come from `foreground`, pixels where mask=false come from `background`.
-fn composite_with_mask(background: &RgbaImage, foreground: &RgbaImage, mask: &[bool]) -> RgbaImage {
-    let (w, h) = background.dimensions();
-    let mut output = background.clone();
-    for y in 0..h {
-        for x in 0..w {
-            if mask[(y * w + x) as usize] {
-                output.put_pixel(x, y, *foreground.get_pixel(x, y));
-            }
-        }
+/// Records the face regions of a frame for the run manifest.
+fn log_face_regions(face_log: &Mutex<Vec<FrameRegions>>, frame_index: usize, regions: &[face_detection::FaceRegion]) -> Result<()> {
+    if regions.is_empty() {
+        return Ok(());
     }
-    output
+    face_log.lock()
+        .map_err(|e| anyhow::anyhow!("Face log mutex poisoned: {}", e))?
+        .push(FrameRegions {
+            frame_index,
+            regions: regions.iter().map(RegionRecord::from).collect(),
+        });
+    Ok(())
 }
 
 /// Alpha-blend two RGBA images: output = a * (1 - alpha) + b * alpha
@@ -139,12 +96,21 @@ struct FrameState {
     prev_scrambled: Option<RgbaImage>,
     /// Face mask from the last keyframe, reused for inter-frames
     /// to avoid running face detection every frame.
-    keyframe_face_mask: Option<Vec<bool>>,
+    keyframe_face_mask: Option<RegionMask>,
+    /// Region mask reused for inter-frames; rebuilt every frame for mask sequences.
+    region_mask: Option<RegionMask>,
+}
+
+/// Whether the region mask changes from frame to frame.
+fn has_mask_sequence(mask_opts: &RegionMaskOptions) -> bool {
+    mask_opts.regions.iter().any(|shape| matches!(shape, RegionShape::MaskSequence(_)))
 }
 
 pub fn process_video(options: &VideoProcessingOptions, progress_callback: impl Fn(f32) + Send + Sync + 'static) -> Result<()> {
     let processor = VideoProcessor::new()?;
-    let scramble_options = options.scramble_options.clone();
+    let (scramble_options, effective_seed, random_steps) = resolve_seed_with_steps(&options.scramble_options);
+    let per_frame_seeds = options.scramble_options.seed.is_none();
+    let face_log = Arc::new(Mutex::new(Vec::new()));
     let temporal_coherence = options.temporal_coherence.clone();
 
     // Load optical flow model if temporal coherence is enabled
@@ -160,16 +126,21 @@ pub fn process_video(options: &VideoProcessingOptions, progress_callback: impl F
         prev_original: None,
         prev_scrambled: None,
         keyframe_face_mask: None,
+        region_mask: None,
     }));
 
     let has_face_detection = scramble_options.face_detection.is_some();
+    let mask_sequence = scramble_options.region_mask.as_ref().is_some_and(has_mask_sequence);
+    let manifest_options = scramble_options.clone();
+    let frame_random_steps = random_steps.clone();
+    let frame_face_log = Arc::clone(&face_log);
 
     processor.process_video(
         &options.input_path,
         &options.output_path,
         move |frame| {
-            let width = frame.width() as u32;
-            let height = frame.height() as u32;
+            let width = frame.width();
+            let height = frame.height();
             let stride = frame.plane_stride()[0] as usize;
             let data = frame.plane_data(0).unwrap();
 
@@ -206,20 +177,23 @@ pub fn process_video(options: &VideoProcessingOptions, progress_callback: impl F
                 let result = if is_keyframe {
                     info!("Frame {}: keyframe, scrambling fresh", frame_idx);
 
-                    // On keyframes, detect faces and cache the mask for inter-frames
+                    let output = scramble_frame(&image, &frame_options(&scramble_options, per_frame_seeds, &frame_random_steps, frame_idx), frame_idx)?;
+
+                    // On keyframes, cache the detected face mask for inter-frames
                     if has_face_detection {
                         let face_opts = scramble_options.face_detection.as_ref().unwrap();
-                        let regions = detect_faces(&image, face_opts)?;
+                        let regions = &output.face_regions;
                         if regions.is_empty() {
                             info!("Frame {}: no faces detected on keyframe", frame_idx);
                             state.keyframe_face_mask = None;
                         } else {
                             info!("Frame {}: detected {} face(s), caching mask", frame_idx, regions.len());
-                            state.keyframe_face_mask = Some(regions_to_mask(&regions, width, height));
+                            state.keyframe_face_mask = Some(RegionMask::from_face_detections(regions, width, height, face_opts));
                         }
                     }
+                    log_face_regions(&frame_face_log, frame_idx, &output.face_regions)?;
 
-                    scramble_frame(&image, &scramble_options)?.to_rgba8()
+                    output.image.to_rgba8()
                 } else {
                     // Inter-frame: compute backward flow (current→prev)
                     let prev_orig = state.prev_original.as_ref()
@@ -240,9 +214,17 @@ pub fn process_video(options: &VideoProcessingOptions, progress_callback: impl F
 
                     let warped = optical_flow::warp_image(prev_scrambled, &flow)?;
 
-                    // Apply face mask compositing if active
-                    let warped = if let Some(ref mask) = state.keyframe_face_mask {
-                        composite_with_mask(&image, &warped, mask)
+                    // Apply region or face mask compositing if active
+                    let warped = if let Some(ref mask_opts) = scramble_options.region_mask {
+                        let mask = match state.region_mask.take() {
+                            Some(mask) if !mask_sequence => mask,
+                            _ => RegionMask::from_options(mask_opts, width, height, frame_idx)?,
+                        };
+                        let warped = composite_with_mask(&image, &warped, &mask, &mask_opts.background_mode);
+                        state.region_mask = Some(mask);
+                        warped
+                    } else if let (Some(mask), Some(face_opts)) = (&state.keyframe_face_mask, &scramble_options.face_detection) {
+                        composite_with_mask(&image, &warped, mask, &face_opts.background_mode)
                     } else {
                         warped
                     };
@@ -255,13 +237,17 @@ pub fn process_video(options: &VideoProcessingOptions, progress_callback: impl F
                             // alpha goes from 0 (pure warped) to ~1 (mostly fresh) as we approach
                             let alpha = 1.0 - (frames_until_keyframe as f32 / (blend_n + 1) as f32);
                             info!("Frame {}: blending toward keyframe, alpha={:.2}", frame_idx, alpha);
-                            let fresh = scramble_frame(&image, &scramble_options)?.to_rgba8();
+                            let fresh = scramble_frame(&image, &frame_options(&scramble_options, per_frame_seeds, &frame_random_steps, frame_idx), frame_idx)?;
+                            log_face_regions(&frame_face_log, frame_idx, &fresh.face_regions)?;
+                            let fresh = fresh.image.to_rgba8();
                             blend_frames(&warped, &fresh, alpha)
                         } else if frames_since_keyframe > 0 && frames_since_keyframe <= blend_n {
                             // Just after keyframe: blend fresh with warped for smooth entry
                             let alpha = frames_since_keyframe as f32 / (blend_n + 1) as f32;
                             info!("Frame {}: blending after keyframe, alpha={:.2}", frame_idx, alpha);
-                            let fresh = scramble_frame(&image, &scramble_options)?.to_rgba8();
+                            let fresh = scramble_frame(&image, &frame_options(&scramble_options, per_frame_seeds, &frame_random_steps, frame_idx), frame_idx)?;
+                            log_face_regions(&frame_face_log, frame_idx, &fresh.face_regions)?;
+                            let fresh = fresh.image.to_rgba8();
                             blend_frames(&fresh, &warped, alpha)
                         } else {
                             warped
@@ -278,7 +264,14 @@ pub fn process_video(options: &VideoProcessingOptions, progress_callback: impl F
                 result
             } else {
                 // No temporal coherence: scramble each frame independently
-                scramble_frame(&image, &scramble_options)?.to_rgba8()
+                let frame_idx = {
+                    let mut state = state.lock().map_err(|e| anyhow::anyhow!("State mutex poisoned: {}", e))?;
+                    state.frame_index += 1;
+                    state.frame_index - 1
+                };
+                let output = scramble_frame(&image, &frame_options(&scramble_options, per_frame_seeds, &frame_random_steps, frame_idx), frame_idx)?;
+                log_face_regions(&frame_face_log, frame_idx, &output.face_regions)?;
+                output.image.to_rgba8()
             };
 
             let data = frame.plane_data_mut(0).unwrap();
@@ -300,5 +293,64 @@ pub fn process_video(options: &VideoProcessingOptions, progress_callback: impl F
         },
     )?;
 
+    let face_regions = std::mem::take(
+        &mut *face_log.lock().map_err(|e| anyhow::anyhow!("Face log mutex poisoned: {}", e))?,
+    );
+    let manifest = VideoManifest {
+        engine_version: ENGINE_VERSION.to_string(),
+        input_path: options.input_path.clone(),
+        input_sha256: hash_file(&options.input_path)?,
+        output_path: options.output_path.clone(),
+        scramble_options: manifest_options,
+        effective_seed,
+        per_frame_seeds,
+        random_step_seeds: (!random_steps.is_empty()).then_some(random_steps),
+        temporal_coherence: options.temporal_coherence.clone(),
+        face_regions,
+    };
+    write_sidecar(&manifest, &options.output_path)?;
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scramble::{PipelineStep, StepSeed};
+
+    fn step_seeds(options: &ScrambleOptions) -> Vec<Option<u64>> {
+        let ScrambleType::Pipeline(steps) = &options.scramble_type else {
+            panic!("not a pipeline");
+        };
+        steps
+            .iter()
+            .map(|step| match step.seed {
+                StepSeed::Fixed(seed) => Some(seed),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn random_step_seeds_vary_per_frame_and_replay() {
+        let options = ScrambleOptions {
+            scramble_type: ScrambleType::Pipeline(vec![
+                PipelineStep { seed: StepSeed::Random, ..PipelineStep::default() },
+                PipelineStep { seed: StepSeed::Fixed(5), ..PipelineStep::default() },
+            ]),
+            seed: Some(1),
+            ..ScrambleOptions::default()
+        };
+        let (resolved, _, random_steps) = resolve_seed_with_steps(&options);
+        assert_eq!(random_steps, vec![0]);
+
+        let first = step_seeds(&frame_options(&resolved, false, &random_steps, 0));
+        let second = step_seeds(&frame_options(&resolved, false, &random_steps, 1));
+        assert_ne!(first[0], second[0]);
+        assert_eq!(first[1], Some(5));
+        assert_eq!(second[1], Some(5));
+
+        // A rerun from the recorded options and step indices gets the same seeds
+        assert_eq!(step_seeds(&frame_options(&resolved, false, &random_steps, 1)), second);
+    }
+}